@@ -88,6 +88,7 @@ impl<'a> AptPackage<'a> {
         file_name = &file_name[pos+1..];
 
         pos = file_name.find(".d")
+            .or_else(|| file_name.find(".u"))
             .or_else(|| file_name.find(".t"))
             .ok_or(ParseAptPackageError::InvalidExtension)?;
 
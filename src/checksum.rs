@@ -1,6 +1,117 @@
 use digest::Digest;
 use hex_view::HexView;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::fs::File;
 use std::io;
+use std::path::Path;
+
+/// The algorithm of a checksum that a download is verified with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Sha1,
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        let algorithm = match name.to_ascii_lowercase().as_str() {
+            "sha1" => Algorithm::Sha1,
+            "sha256" => Algorithm::Sha256,
+            "sha512" => Algorithm::Sha512,
+            _ => return None,
+        };
+
+        Some(algorithm)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// A hasher which digests data as it is received.
+    pub fn hasher(self) -> Box<dyn digest::DynDigest + Send> {
+        match self {
+            Algorithm::Sha1 => Box::new(Sha1::new()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+            Algorithm::Sha512 => Box::new(Sha512::new()),
+        }
+    }
+
+    pub fn digest<R: io::Read>(self, reader: R) -> io::Result<String> {
+        match self {
+            Algorithm::Sha1 => hasher::<Sha1, R>(reader),
+            Algorithm::Sha256 => hasher::<Sha256, R>(reader),
+            Algorithm::Sha512 => hasher::<Sha512, R>(reader),
+        }
+    }
+
+    /// Writes the digest as it is written in the config. SHA256 digests have no prefix.
+    pub fn format(self, digest: &str) -> String {
+        match self {
+            Algorithm::Sha256 => digest.to_owned(),
+            _ => [self.name(), ":", digest].concat(),
+        }
+    }
+}
+
+/// A checksum from the config, written as `<algorithm>:<digest>`, or as a SHA256 digest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Checksum<'a> {
+    pub algorithm: Algorithm,
+    pub digest: &'a str,
+}
+
+impl<'a> Checksum<'a> {
+    pub fn parse(checksum: &'a str) -> io::Result<Self> {
+        let (algorithm, digest) = match checksum.split_once(':') {
+            Some((name, digest)) => {
+                let algorithm = Algorithm::from_name(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("unsupported checksum algorithm: {}", name),
+                    )
+                })?;
+
+                (algorithm, digest)
+            }
+            None => (Algorithm::Sha256, checksum),
+        };
+
+        let length = algorithm.hasher().output_size() * 2;
+        if digest.len() != length || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} checksum must be {} hexadecimal digits: {}",
+                    algorithm.name(),
+                    length,
+                    digest
+                ),
+            ));
+        }
+
+        Ok(Checksum { algorithm, digest })
+    }
+
+    /// Computes the digest of the file with the algorithm of this checksum.
+    pub fn digest_of(&self, path: &Path) -> io::Result<String> {
+        File::open(path).and_then(|file| self.algorithm.digest(file))
+    }
+
+    pub fn matches(&self, digest: &str) -> bool {
+        self.digest.eq_ignore_ascii_case(digest)
+    }
+}
 
 pub(crate) fn hasher<H: Digest, R: io::Read>(mut reader: R) -> io::Result<String> {
     let mut buffer = [0u8; 8 * 1024];
@@ -16,3 +127,69 @@ pub(crate) fn hasher<H: Digest, R: io::Read>(mut reader: R) -> io::Result<String
 
     Ok(format!("{:x}", HexView::from(hasher.finalize().as_slice())))
 }
+
+/// The digests of a file which are listed in the dist indices.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Digests {
+    pub md5sum: String,
+    pub sha1: String,
+    pub sha256: String,
+    pub sha512: String,
+}
+
+impl Digests {
+    /// Computes each digest of the file at the given path.
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        File::open(path).and_then(Self::from_reader)
+    }
+
+    /// Computes each digest in a single pass over the reader. Each block that is read is
+    /// given to every hasher in parallel.
+    pub fn from_reader<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let (mut md5, mut sha1, mut sha256, mut sha512) =
+            (Md5::new(), Sha1::new(), Sha256::new(), Sha512::new());
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            let block = &buffer[..read];
+            rayon::join(
+                || rayon::join(|| md5.update(block), || sha1.update(block)),
+                || rayon::join(|| sha256.update(block), || sha512.update(block)),
+            );
+        }
+
+        let hex = |digest: &[u8]| format!("{:x}", HexView::from(digest));
+        Ok(Digests {
+            md5sum: hex(&md5.finalize()),
+            sha1: hex(&sha1.finalize()),
+            sha256: hex(&sha256.finalize()),
+            sha512: hex(&sha512.finalize()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_checksums() {
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let checksum = Checksum::parse(sha256).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha256);
+        assert!(checksum.matches(&Algorithm::Sha256.digest(&b""[..]).unwrap()));
+
+        let sha1 = "sha1:DA39A3EE5E6B4B0D3255BFEF95601890AFD80709";
+        let checksum = Checksum::parse(sha1).unwrap();
+        assert_eq!(checksum.algorithm, Algorithm::Sha1);
+        assert!(checksum.matches(&Algorithm::Sha1.digest(&b""[..]).unwrap()));
+
+        assert!(Checksum::parse(&["sha512:", sha256].concat()).is_err());
+        assert!(Checksum::parse(&["md4:", sha256].concat()).is_err());
+    }
+}
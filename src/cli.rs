@@ -9,9 +9,12 @@ pub enum Action<'a> {
     Fetch(&'a str),
     FetchConfig,
     Migrate(Vec<&'a str>, &'a str, &'a str),
+    Outdated(bool),
     Pool,
     Remove(Vec<&'a str>),
+    Rollback,
     Update(&'a str, &'a str),
+    UpdateDirect,
     UpdateRepository,
 }
 
@@ -28,11 +31,17 @@ impl<'a> Action<'a> {
                 _ => Action::UpdateRepository,
             },
             ("clean", _) => Action::Clean,
+            ("dist", Some(dist)) => match dist.subcommand() {
+                ("rollback", _) => Action::Rollback,
+                _ => unreachable!(),
+            },
             ("config", Some(config)) => config.value_of("key").map_or(Action::FetchConfig, |key| {
                 config
                     .value_of("value")
                     .map_or(Action::Fetch(key), |value| Action::Update(key, value))
             }),
+            ("update", _) => Action::UpdateDirect,
+            ("outdated", Some(outdated)) => Action::Outdated(outdated.is_present("json")),
             ("remove", Some(pkgs)) => Action::Remove(pkgs.values_of("packages").unwrap().collect()),
             ("migrate", Some(migrate)) => Action::Migrate(
                 migrate.values_of("packages").unwrap().collect(),
@@ -1,5 +1,6 @@
 use std::ffi::OsStr;
 use std::io::{self, BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
 use std::process::{self, Stdio};
 use std::thread;
 
@@ -20,6 +21,11 @@ impl Command {
         self
     }
 
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.0.current_dir(dir);
+        self
+    }
+
     pub fn env(&mut self, key: &str, value: &str) {
         self.0.env(key, value);
     }
@@ -1,21 +1,94 @@
+use crate::checksum::Digests;
 use bus_writer::BusWriter;
 use deflate::Compression;
 use deflate::write::GzEncoder;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
+use std::sync::Mutex;
+use walkdir::WalkDir;
 use xz2::write::XzEncoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
 
 pub const UNCOMPRESSED: u8 = 0b1;
 pub const GZ_COMPRESS: u8 = 0b10;
 pub const XZ_COMPRESS: u8 = 0b100;
 pub const ZSTD_COMPRESS: u8 = 0b1000;
 
-pub trait SyncWrite: Send + Sync + io::Write {}
-impl<T: Send + Sync + io::Write> SyncWrite for T {}
+/// Where each index is additionally stored by its digests, for clients using `Acquire-By-Hash`.
+/// Clients request an index by the strongest hash that the Release file lists for it.
+pub const BY_HASH: [&str; 2] = ["by-hash/SHA256", "by-hash/SHA512"];
 
-pub fn compress<R: io::Read>(name: &str, path: &Path, stream: R, support: u8) -> io::Result<()> {
-    inner_compress(name, path, stream, support).map_err(|why| {
+/// A variant of an index being written, which must be explicitly finished so that errors writing
+/// the end of its archive are not lost.
+enum Destination {
+    Plain(File),
+    Gz(Box<GzEncoder<File>>),
+    Xz(XzEncoder<File>),
+    /// The zstd encoder is not `Sync`.
+    Zstd(Mutex<ZstdEncoder<'static, File>>),
+}
+
+impl Destination {
+    fn finish(self) -> io::Result<()> {
+        let file = match self {
+            Destination::Plain(file) => file,
+            Destination::Gz(encoder) => encoder.finish()?,
+            Destination::Xz(encoder) => encoder.finish()?,
+            Destination::Zstd(encoder) => encoder
+                .into_inner()
+                .map_err(|_| io::Error::other("zstd encoder was poisoned"))?
+                .finish()?,
+        };
+
+        file.sync_all()
+    }
+}
+
+impl io::Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Destination::Plain(file) => file.write(buf),
+            Destination::Gz(encoder) => encoder.write(buf),
+            Destination::Xz(encoder) => encoder.write(buf),
+            Destination::Zstd(encoder) => encoder
+                .get_mut()
+                .map_err(|_| io::Error::other("zstd encoder was poisoned"))?
+                .write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Destination::Plain(file) => file.flush(),
+            Destination::Gz(encoder) => encoder.flush(),
+            Destination::Xz(encoder) => encoder.flush(),
+            Destination::Zstd(encoder) => encoder
+                .get_mut()
+                .map_err(|_| io::Error::other("zstd encoder was poisoned"))?
+                .flush(),
+        }
+    }
+}
+
+/// Describes which variants of an index will be written by `compress`.
+#[derive(Clone, Copy, Debug)]
+pub struct CompressOptions {
+    pub support: u8,
+    pub zstd_level: i32,
+    /// If set, each variant will also be stored in each `BY_HASH` directory, with its
+    /// modification time set to this timestamp to mark the generation it belongs to.
+    pub by_hash: Option<i64>,
+}
+
+pub fn compress<R: io::Read>(
+    name: &str,
+    path: &Path,
+    stream: R,
+    options: &CompressOptions,
+) -> io::Result<()> {
+    inner_compress(name, path, stream, options).map_err(|why| {
         io::Error::new(
             io::ErrorKind::Other,
             format!(
@@ -28,40 +101,130 @@ pub fn compress<R: io::Read>(name: &str, path: &Path, stream: R, support: u8) ->
     })
 }
 
-fn inner_compress<R: io::Read>(name: &str, path: &Path, stream: R, support: u8) -> io::Result<()> {
+fn inner_compress<R: io::Read>(
+    name: &str,
+    path: &Path,
+    stream: R,
+    options: &CompressOptions,
+) -> io::Result<()> {
+    let support = options.support;
     if support == 0 {
         return Ok(());
     }
 
+    let mut files = Vec::new();
     let mut destinations = {
-        let mut writers: Vec<Box<dyn SyncWrite>> = Vec::new();
+        let mut writers = Vec::new();
         if support & UNCOMPRESSED != 0 {
-            writers.push(Box::new(File::create(path.join(name))?));
+            let file = path.join(name);
+            writers.push(Destination::Plain(File::create(&file)?));
+            files.push(file);
         }
 
         if support & GZ_COMPRESS != 0 {
-            let gz_file = File::create(path.join([name, ".gz"].concat()))?;
-            writers.push(Box::new(GzEncoder::new(gz_file, Compression::Best)));
+            let file = path.join([name, ".gz"].concat());
+            let gz_file = File::create(&file)?;
+            let encoder = GzEncoder::new(gz_file, Compression::Best);
+            writers.push(Destination::Gz(Box::new(encoder)));
+            files.push(file);
         }
 
         if support & XZ_COMPRESS != 0 {
-            let xz_file = File::create(path.join([name, ".xz"].concat()))?;
-            writers.push(Box::new(XzEncoder::new(xz_file, 9)));
+            let file = path.join([name, ".xz"].concat());
+            let xz_file = File::create(&file)?;
+            writers.push(Destination::Xz(XzEncoder::new(xz_file, 9)));
+            files.push(file);
+        }
+
+        if support & ZSTD_COMPRESS != 0 {
+            let file = path.join([name, ".zst"].concat());
+            let zst_file = File::create(&file)?;
+            let encoder = ZstdEncoder::new(zst_file, options.zstd_level)?;
+            writers.push(Destination::Zstd(Mutex::new(encoder)));
+            files.push(file);
         }
 
         writers
     };
 
     log::info!(
-        "compressing {} to {}: uncompressed: {}, gzip: {}, xz: {}",
+        "compressing {} to {}: uncompressed: {}, gzip: {}, xz: {}, zstd: {}",
         name,
         path.display(),
         support & UNCOMPRESSED != 0,
         support & GZ_COMPRESS != 0,
-        support & XZ_COMPRESS != 0
+        support & XZ_COMPRESS != 0,
+        support & ZSTD_COMPRESS != 0
     );
 
     BusWriter::new(stream, &mut destinations, |_| {}, || false).write()?;
 
+    for destination in destinations {
+        destination.finish()?;
+    }
+
+    if let Some(generation) = options.by_hash {
+        for directory in &BY_HASH {
+            fs::create_dir_all(path.join(directory))?;
+        }
+
+        for file in &files {
+            store_by_hash(file, path, generation)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies the file into each by-hash directory of its parent, if it is not already there, and
+/// marks it as belonging to the given generation.
+fn store_by_hash(file: &Path, parent: &Path, generation: i64) -> io::Result<()> {
+    let digests = Digests::from_path(file)?;
+    for (directory, digest) in BY_HASH.iter().zip([&digests.sha256, &digests.sha512]) {
+        let destination = parent.join(directory).join(digest);
+        if !destination.exists() {
+            fs::copy(file, &destination)?;
+        }
+
+        utime::set_file_times(&destination, generation, generation)?;
+    }
+
+    Ok(())
+}
+
+/// Removes files from each by-hash directory in the dist directory, which do not belong to the
+/// current generation, or one of the `keep` generations before it.
+pub fn prune_by_hash(base: &Path, keep: usize) -> io::Result<()> {
+    let directories = WalkDir::new(base)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_dir()
+                && BY_HASH
+                    .iter()
+                    .any(|directory| entry.path().ends_with(directory))
+        })
+        .map(|entry| entry.into_path())
+        .collect::<Vec<_>>();
+
+    for directory in directories {
+        let mut files = Vec::new();
+        for entry in directory.read_dir()? {
+            let entry = entry?;
+            files.push((entry.path(), entry.metadata()?.mtime()));
+        }
+
+        let mut generations = files.iter().map(|(_, mtime)| *mtime).collect::<Vec<_>>();
+        generations.sort_unstable_by(|a, b| b.cmp(a));
+        generations.dedup();
+
+        if let Some(&oldest) = generations.get(keep + 1) {
+            for (file, _) in files.iter().filter(|(_, mtime)| *mtime <= oldest) {
+                log::info!("removing stale by-hash index at {}", file.display());
+                fs::remove_file(file)?;
+            }
+        }
+    }
+
     Ok(())
 }
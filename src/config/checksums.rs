@@ -0,0 +1,17 @@
+use crate::checksum::Algorithm;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A remote file which lists the checksums of the files beside it, such as `SHA256SUMS`.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct ChecksumsFile {
+    /// The URL of the file, which may contain `${name}` and `${version}`.
+    pub url: String,
+    /// The algorithm of the checksums within the file.
+    #[serde(default, skip_serializing_if = "super::is_default")]
+    pub algorithm: Algorithm,
+    /// The URL of a detached OpenPGP signature of the file, such as `SHA256SUMS.gpg`.
+    pub signature: Option<String>,
+    /// An armored public key that the signature must be made by.
+    pub key: Option<PathBuf>,
+}
@@ -0,0 +1,98 @@
+use crate::compress::*;
+use serde::{Deserialize, Serialize};
+
+/// A format which the dist indices may be published in.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionFormat {
+    /// The uncompressed index, which APT still expects to be listed.
+    None,
+    Gz,
+    Xz,
+    Zstd,
+}
+
+/// Controls which compressed variants of the `Packages`, `Sources`, and `Contents` indices
+/// will be published for a suite.
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub struct Compression {
+    #[serde(
+        default = "default_formats",
+        skip_serializing_if = "is_default_formats"
+    )]
+    pub formats: Vec<CompressionFormat>,
+    #[serde(
+        default = "default_zstd_level",
+        skip_serializing_if = "is_default_zstd_level"
+    )]
+    pub zstd_level: i32,
+}
+
+impl Compression {
+    /// The flags which `compress` expects for the configured formats.
+    pub fn flags(&self) -> u8 {
+        self.formats.iter().fold(0, |flags, format| {
+            flags
+                | match format {
+                    CompressionFormat::None => UNCOMPRESSED,
+                    CompressionFormat::Gz => GZ_COMPRESS,
+                    CompressionFormat::Xz => XZ_COMPRESS,
+                    CompressionFormat::Zstd => ZSTD_COMPRESS,
+                }
+        })
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            formats: default_formats(),
+            zstd_level: default_zstd_level(),
+        }
+    }
+}
+
+fn default_formats() -> Vec<CompressionFormat> {
+    vec![
+        CompressionFormat::None,
+        CompressionFormat::Gz,
+        CompressionFormat::Xz,
+        CompressionFormat::Zstd,
+    ]
+}
+fn default_zstd_level() -> i32 {
+    19
+}
+fn is_default_formats(formats: &Vec<CompressionFormat>) -> bool {
+    *formats == default_formats()
+}
+fn is_default_zstd_level(level: &i32) -> bool {
+    *level == default_zstd_level()
+}
+
+/// Publishes each index under its digest, so that clients may fetch indices by their hash
+/// while the repository is being updated.
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub struct ByHash {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How many generations of indices to keep, in addition to the current generation.
+    #[serde(default = "default_keep", skip_serializing_if = "is_default_keep")]
+    pub keep: usize,
+}
+
+impl Default for ByHash {
+    fn default() -> Self {
+        ByHash {
+            enabled: false,
+            keep: default_keep(),
+        }
+    }
+}
+
+fn default_keep() -> usize {
+    3
+}
+fn is_default_keep(keep: &usize) -> bool {
+    *keep == default_keep()
+}
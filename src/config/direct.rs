@@ -1,22 +1,98 @@
-use super::{ConfigError, ConfigFetch};
+use super::{ChecksumsFile, ConfigError, ConfigFetch, Repackage};
 use crate::debian::DEB_SOURCE_EXTENSIONS;
-use crate::misc;
+use crate::debian::arch::{self, Architectures};
 use crate::url::UrlTokenizer;
+use serde::de::{self, Deserializer};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::io;
 use std::path::PathBuf;
 
+/// Where the text that versions are found within is read from.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateSource {
+    /// The body of the page at the URL.
+    #[default]
+    Page,
+    /// The URL that is reached after following redirects, such as from a download link.
+    Redirect,
+}
+
+/// How to find the newest version of a direct package with `debrep update`.
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct Update {
-    pub source: String,
+    #[serde(default, skip_serializing_if = "super::is_default")]
+    pub source: UpdateSource,
     pub url: String,
+    /// The text which precedes a version.
+    #[serde(deserialize_with = "deserialize_marker")]
     pub after: String,
+    /// The text which follows a version.
+    #[serde(deserialize_with = "deserialize_marker")]
     pub before: String,
+    /// Only lines which contain this text will be searched for versions.
     pub contains: Option<String>,
+    /// The names of the URLs whose checksums will be updated, if not all of them.
     pub build_from: Option<Vec<String>>,
 }
 
+impl Update {
+    /// Finds every version which is between the `after` and `before` markers.
+    pub fn versions<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut versions = Vec::new();
+        let lines = text.lines().filter(|line| {
+            self.contains
+                .as_ref()
+                .is_none_or(|x| line.contains(x.as_str()))
+        });
+
+        for mut line in lines {
+            while let Some(start) = line.find(&self.after) {
+                let remaining = line.len();
+                line = &line[start + self.after.len()..];
+                let Some(end) = line.find(&self.before) else {
+                    break;
+                };
+
+                // Text which cannot be a Debian version is not between a matching pair of markers.
+                let version = &line[..end];
+                if !version.is_empty()
+                    && version
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || ".+~:-".contains(c))
+                {
+                    versions.push(version);
+                    line = &line[end..];
+                }
+
+                // Empty markers would otherwise be found at the same position forever.
+                if line.len() == remaining {
+                    let mut chars = line.chars();
+                    if chars.next().is_none() {
+                        break;
+                    }
+
+                    line = chars.as_str();
+                }
+            }
+        }
+
+        versions
+    }
+}
+
+fn deserialize_marker<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let marker = String::deserialize(deserializer)?;
+    if marker.is_empty() {
+        Err(de::Error::custom(
+            "the markers of a version must not be empty",
+        ))
+    } else {
+        Ok(marker)
+    }
+}
+
 /// Stores where the file can be downloaded, and where that file should be stored.
 #[derive(Debug)]
 pub struct BinaryDestinations {
@@ -30,7 +106,10 @@ pub struct BinaryDestinations {
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct DirectPath {
+    /// Written as `<algorithm>:<digest>`, or as a SHA256 digest.
     pub checksum: Option<String>,
+    /// Where the checksum is looked up, if it is not given.
+    pub checksums: Option<ChecksumsFile>,
     pub arch: Option<String>,
     pub name: Option<String>,
     pub url: String,
@@ -44,6 +123,10 @@ pub struct Direct {
     pub urls: Vec<DirectPath>,
     pub checksum: Option<String>,
     pub update: Option<Update>,
+    /// How many of the newest versions of this package in the pool will be indexed.
+    pub index_versions: Option<usize>,
+    /// Changes to make to the control and data archives of the package.
+    pub repackage: Option<Repackage>,
 }
 
 impl Direct {
@@ -51,6 +134,7 @@ impl Direct {
         &self,
         suite: &str,
         component: &str,
+        architectures: &Architectures,
     ) -> io::Result<Vec<BinaryDestinations>> {
         let mut output = Vec::new();
 
@@ -109,7 +193,7 @@ impl Direct {
                 let extension = &file[ext_pos..];
                 let arch = match file_item.arch.as_ref() {
                     Some(ref arch) => arch.as_str(),
-                    None => misc::get_arch_from_stem(&file[..ext_pos - 1]),
+                    None => architectures.from_stem(&file[..ext_pos - 1]),
                 };
 
                 let filename = gen_filename(name, &self.version, arch, extension);
@@ -117,17 +201,27 @@ impl Direct {
                     "tar.gz" | "tar.xz" | "tar.zst" | "dsc" => {
                         ["/", component, "/source/"].concat()
                     }
-                    _ => ["/", component, "/binary-", arch, "/"].concat(),
+                    _ => ["/", component, "/", &arch::binary_dir(arch), "/"].concat(),
                 };
 
+                let mut pool_filename = Cow::Borrowed(filename.as_str());
                 if extension == "deb" {
                     let base = format!("assets/replace/{}{}/{}/", suite, dst, name);
                     let files = PathBuf::from([&base, "files"].concat());
-                    if files.exists() {
+                    if files.exists() || self.repackage.is_some() {
                         let replace = PathBuf::from([base.as_str(), filename.as_str()].concat());
                         log::debug!("setting asset target to {:?}", replace);
                         assets = Some((files, replace));
                     }
+
+                    if let Some(suffix) = self
+                        .repackage
+                        .as_ref()
+                        .and_then(|r| r.version_suffix.as_ref())
+                    {
+                        let version = [self.version.as_str(), suffix].concat();
+                        pool_filename = Cow::Owned(gen_filename(name, &version, arch, extension));
+                    }
                 }
 
                 PathBuf::from(
@@ -139,7 +233,7 @@ impl Direct {
                         "/",
                         name,
                         "/",
-                        &filename,
+                        &pool_filename,
                     ]
                     .concat(),
                 )
@@ -172,3 +266,49 @@ impl ConfigFetch for Direct {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_versions() {
+        let update: Update = toml::from_str(
+            r#"
+            url = "https://example.com/downloads"
+            after = "example_"
+            before = "_amd64.deb"
+            contains = "href"
+            "#,
+        )
+        .unwrap();
+
+        let page = r#"
+            <a href="example_1.2.0_amd64.deb">1.2.0</a> <a href="example_1.10.1_amd64.deb">1.10.1</a>
+            <p>example_9.9.9_amd64.deb is not a link</p>
+            <a href="example_1.9.0_arm64.deb">1.9.0</a> <a href="example_2.0.0_amd64.deb">2.0.0</a>
+        "#;
+
+        assert_eq!(update.source, UpdateSource::Page);
+        assert_eq!(update.versions(page), ["1.2.0", "1.10.1", "2.0.0"]);
+    }
+
+    #[test]
+    fn update_versions_with_empty_markers() {
+        let parse = |after: &str, before: &str| {
+            toml::from_str::<Update>(&format!(
+                "url = \"https://example.com\"\nafter = {:?}\nbefore = {:?}",
+                after, before
+            ))
+        };
+
+        assert!(parse("", "_amd64.deb").is_err());
+        assert!(parse("example_", "").is_err());
+
+        let mut update = parse("example_", "_amd64.deb").unwrap();
+        update.after.clear();
+        assert_eq!(update.versions("1.0_amd64.deb"), ["1.0"]);
+        update.before.clear();
+        assert!(update.versions("example_1.0_amd64.deb").is_empty());
+    }
+}
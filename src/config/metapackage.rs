@@ -0,0 +1,157 @@
+use crate::debian::control::Fields;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+
+/// A package which contains no files, and only declares relationships with other packages.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct Metapackage {
+    #[serde(deserialize_with = "deserialize_name")]
+    pub name: String,
+    pub version: String,
+    /// The summary on the first line, followed by the long description.
+    pub description: String,
+    #[serde(default = "default_arch", skip_serializing_if = "is_default_arch")]
+    pub arch: String,
+    #[serde(
+        default = "default_section",
+        skip_serializing_if = "is_default_section"
+    )]
+    pub section: String,
+    /// Defaults to the origin and email of the suite.
+    pub maintainer: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recommends: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provides: Vec<String>,
+}
+
+impl Metapackage {
+    /// The fields of the control file of the package.
+    pub fn fields(&self, maintainer: &str) -> Fields {
+        let mut fields: Fields = vec![
+            ("Package".into(), self.name.clone()),
+            ("Version".into(), self.version.clone()),
+            ("Architecture".into(), self.arch.clone()),
+            (
+                "Maintainer".into(),
+                self.maintainer.as_deref().unwrap_or(maintainer).to_owned(),
+            ),
+            ("Priority".into(), "optional".into()),
+            ("Section".into(), self.section.clone()),
+        ];
+
+        let relations = [
+            ("Depends", &self.depends),
+            ("Recommends", &self.recommends),
+            ("Conflicts", &self.conflicts),
+            ("Provides", &self.provides),
+        ];
+
+        for (key, packages) in relations {
+            if !packages.is_empty() {
+                fields.push((key.into(), packages.join(", ")));
+            }
+        }
+
+        // Each line of the long description is indented, and blank lines are written as ` .`.
+        let mut lines = self.description.trim().lines();
+        let mut description = lines.next().unwrap_or_default().to_owned();
+        for line in lines {
+            description.push_str("\n ");
+            description.push_str(if line.trim().is_empty() { "." } else { line });
+        }
+
+        fields.push(("Description".into(), description));
+        fields
+    }
+}
+
+/// Package names must be at least two characters long, begin with an alphanumeric character,
+/// and contain only lowercase letters, digits, `+`, `-`, and `.`.
+fn is_package_name(name: &str) -> bool {
+    name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+}
+
+fn deserialize_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    if is_package_name(&name) {
+        Ok(name)
+    } else {
+        Err(de::Error::custom(format!(
+            "invalid package name: {:?}",
+            name
+        )))
+    }
+}
+
+fn default_arch() -> String {
+    "all".into()
+}
+
+fn default_section() -> String {
+    "metapackages".into()
+}
+
+fn is_default_arch(arch: &str) -> bool {
+    arch == default_arch()
+}
+
+fn is_default_section(section: &str) -> bool {
+    section == default_section()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debian::control;
+
+    #[test]
+    fn metapackage_fields() {
+        let metapackage: Metapackage = toml::from_str(
+            r#"
+            name = "pop-desktop"
+            version = "1.0"
+            depends = ["gnome-shell", "pop-theme (>= 2.0)"]
+            provides = ["desktop"]
+            description = "Pop!_OS desktop\nInstalls the desktop environment.\n\nAnd its theme.\n"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metapackage.fields("Pop OS <info@system76.com>"),
+            control::parse(
+                "Package: pop-desktop\nVersion: 1.0\nArchitecture: all\n\
+                 Maintainer: Pop OS <info@system76.com>\nPriority: optional\n\
+                 Section: metapackages\nDepends: gnome-shell, pop-theme (>= 2.0)\n\
+                 Provides: desktop\nDescription: Pop!_OS desktop\n \
+                 Installs the desktop environment.\n .\n And its theme.\n"
+            )
+        );
+    }
+
+    #[test]
+    fn metapackage_names() {
+        let parse = |name: &str| {
+            toml::from_str::<Metapackage>(&format!(
+                "name = {:?}\nversion = \"1.0\"\ndescription = \"a\"",
+                name
+            ))
+        };
+
+        assert!(parse("pop-desktop").is_ok());
+        assert!(parse("g++").is_ok());
+        assert!(parse("0ad.data").is_ok());
+        for name in ["", "a", "-a", "Pop", "pop desktop", "pop_desktop", "pöp"] {
+            assert!(parse(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+}
@@ -5,15 +5,31 @@ use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::PathBuf;
 
+use crate::compress::CompressOptions;
+use crate::debian::arch::{ArchAll, Architectures};
+use crate::debian::archive::DebCompression;
+use crate::debian::{DebugSymbols, DuplicateFiles};
 use crate::misc;
 use toml::{self, de};
 
+mod checksums;
+mod compression;
 mod direct;
+mod metapackage;
+mod release;
+mod repackage;
 mod repos;
+mod signing;
 mod source;
 
+pub use self::checksums::*;
+pub use self::compression::*;
 pub use self::direct::*;
+pub use self::metapackage::*;
+pub use self::release::*;
+pub use self::repackage::*;
 pub use self::repos::*;
+pub use self::signing::*;
 pub use self::source::*;
 
 #[derive(Debug, thiserror::Error)]
@@ -48,24 +64,100 @@ pub struct Config {
     pub origin: String,
     pub label: String,
     pub email: String,
-    #[serde(default = "default_architectures")]
+    #[serde(
+        default = "default_architectures",
+        skip_serializing_if = "is_default_architectures"
+    )]
     pub architectures: Vec<String>,
+    /// Whether `all` packages are also indexed with the packages of every other architecture.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub arch_all: ArchAll,
     /// Packages which are already in the deb format.
     pub direct: Option<Vec<Direct>>,
     /// Projects which can be built from source.
     pub source: Option<Vec<Source>>,
+    /// Packages which only declare relationships with other packages.
+    pub metapackage: Option<Vec<Metapackage>>,
     /// Repos to pull packages from.
     pub repos: Option<Vec<Repo>>,
-    #[serde(default = "default_component")]
+    #[serde(
+        default = "default_component",
+        skip_serializing_if = "is_default_component"
+    )]
     pub default_component: String,
+    /// How many of the newest versions of each package in the pool will be indexed.
+    #[serde(
+        default = "default_index_versions",
+        skip_serializing_if = "is_default_index_versions"
+    )]
+    pub index_versions: usize,
     pub extra_repos: Option<Vec<String>>,
+    /// Which compressed variants of the dist indices to publish.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub compression: Compression,
+    /// Whether to publish indices for clients which support `Acquire-By-Hash`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub by_hash: ByHash,
+    /// Where debug symbols are indexed, so that clients which do not need them are not burdened.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub debug_symbols: DebugSymbols,
+    /// What to do when packages ship the same file without declaring a relationship.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub duplicate_files: DuplicateFiles,
+    /// The compression of the archives within the debs that are built natively.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub deb_compression: DebCompression,
+    /// How many files may be downloaded at the same time.
+    #[serde(
+        default = "default_download_jobs",
+        skip_serializing_if = "is_default_download_jobs"
+    )]
+    pub download_jobs: usize,
+    /// How many files may be downloaded at the same time from each host.
+    #[serde(
+        default = "default_download_jobs_per_host",
+        skip_serializing_if = "is_default_download_jobs_per_host"
+    )]
+    pub download_jobs_per_host: usize,
+    /// Moves long descriptions out of the Packages indices, into Translation indices.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub translations: bool,
+    /// Additional fields for the Release files.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub release: ReleaseFields,
+    /// How the release files will be signed.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub signing: Signing,
     #[serde(skip)]
     pub extra_keys: Vec<PathBuf>,
 }
 
 impl Config {
+    /// The options that each index of this suite will be compressed with, for the generation
+    /// created at the given timestamp.
+    pub fn compress_options(&self, generation: i64) -> CompressOptions {
+        CompressOptions {
+            support: self.compression.flags(),
+            zstd_level: self.compression.zstd_level,
+            by_hash: if self.by_hash.enabled {
+                Some(generation)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The architectures which binaries of this suite are built and published for.
+    pub fn arches(&self) -> Architectures {
+        Architectures::new(&self.architectures)
+    }
+
     pub fn write_to_disk(&self) -> Result<(), ParsingError> {
-        toml::ser::to_vec(self)
+        // TOML requires that plain values are written before tables. Serializing through a
+        // `Value` reorders them, where serializing the struct would fail on the first value
+        // after a table.
+        toml::Value::try_from(self)
+            .and_then(|value| toml::ser::to_vec(&value))
             .map_err(|why| ParsingError::TomlSerialize { why })
             .and_then(|data| {
                 File::create(&self.path)
@@ -98,14 +190,76 @@ impl Config {
     pub fn package_exists(&self, filename: &str) -> bool {
         self.direct_exists(filename) || self.source_exists(filename)
     }
+
+    /// How many versions of a binary package will be indexed, which may be overridden by the
+    /// config of the direct package that it is downloaded by, or of the source package that it
+    /// was built from, as named by its `Source` field.
+    pub fn index_versions(&self, package: &str, source: Option<&str>) -> usize {
+        let direct = self
+            .direct
+            .iter()
+            .flatten()
+            .find(|direct| {
+                direct
+                    .urls
+                    .iter()
+                    .any(|url| url.name.as_deref().unwrap_or(&direct.name) == package)
+            })
+            .and_then(|direct| direct.index_versions);
+
+        // The version of the source is given when it differs from the version of the binary.
+        let source_name = source
+            .and_then(|source| source.split_whitespace().next())
+            .unwrap_or(package);
+
+        let source = || {
+            self.source
+                .iter()
+                .flatten()
+                .find(|source| source.name == source_name)
+                .and_then(|source| source.index_versions)
+        };
+
+        direct.or_else(source).unwrap_or(self.index_versions).max(1)
+    }
 }
 
 fn default_architectures() -> Vec<String> {
-    vec!["amd64".into(), "i368".into()]
+    vec!["amd64".into(), "i386".into()]
 }
 fn default_component() -> String {
     "main".into()
 }
+fn default_index_versions() -> usize {
+    1
+}
+fn default_download_jobs() -> usize {
+    8
+}
+fn default_download_jobs_per_host() -> usize {
+    4
+}
+
+/// Fields which have their default value are not written to the config, so that a config
+/// which is rewritten by `debrep update` will follow any changes to the defaults.
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+fn is_default_architectures(architectures: &Vec<String>) -> bool {
+    *architectures == default_architectures()
+}
+fn is_default_component(component: &str) -> bool {
+    component == default_component()
+}
+fn is_default_index_versions(versions: &usize) -> bool {
+    *versions == default_index_versions()
+}
+fn is_default_download_jobs(jobs: &usize) -> bool {
+    *jobs == default_download_jobs()
+}
+fn is_default_download_jobs_per_host(jobs: &usize) -> bool {
+    *jobs == default_download_jobs_per_host()
+}
 
 /// Methods for fetching and updating values from the in-memory representation of the TOML spec.
 pub trait ConfigFetch {
@@ -232,3 +386,112 @@ pub fn parse(path: PathBuf) -> Result<Config, ParsingError> {
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_versions_of_binaries() {
+        let config: Config = toml::from_str(
+            r#"
+            archive = "stable"
+            version = "1.0"
+            origin = "Test"
+            label = "Test"
+            email = "test@example.com"
+            index_versions = 2
+
+            [[source]]
+            name = "foo"
+            index_versions = 5
+
+            [[direct]]
+            name = "bar"
+            version = "1.0"
+            index_versions = 3
+            urls = [
+                { name = "libbar1", url = "https://example.com/libbar1_${version}_amd64.deb" },
+                { url = "https://example.com/bar_${version}_amd64.deb" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        // Both binaries of the foo source are found by their Source field.
+        assert_eq!(config.index_versions("foo-utils", Some("foo")), 5);
+        assert_eq!(config.index_versions("libfoo1", Some("foo (1.2-1)")), 5);
+        assert_eq!(config.index_versions("foo", None), 5);
+
+        assert_eq!(config.index_versions("libbar1", None), 3);
+        assert_eq!(config.index_versions("bar", None), 3);
+        assert_eq!(config.index_versions("baz", Some("baz")), 2);
+    }
+
+    #[test]
+    fn defaults_are_not_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config: Config = toml::from_str(
+            r#"
+            archive = "stable"
+            version = "1.0"
+            origin = "Test"
+            label = "Test"
+            email = "test@example.com"
+
+            [by_hash]
+            enabled = true
+
+            [[direct]]
+            name = "bar"
+            version = "1.0"
+            urls = [{ url = "https://example.com/bar_${version}_amd64.deb" }]
+
+            [direct.update]
+            url = "https://example.com/downloads"
+            after = "bar_"
+            before = "_amd64.deb"
+
+            [[source]]
+            name = "foo"
+
+            [[metapackage]]
+            name = "desktop"
+            version = "1.0"
+            description = "Desktop"
+            "#,
+        )
+        .unwrap();
+
+        config.path = dir.path().join("stable.toml");
+        config.write_to_disk().unwrap();
+
+        let written = fs::read_to_string(&config.path).unwrap();
+        for key in [
+            "architectures",
+            "default_component",
+            "index_versions",
+            "download_jobs",
+            "compression",
+            "signing",
+            "release",
+            "debug_symbols",
+            "keep",
+            "source",
+            "arch",
+            "section",
+            "depends",
+            "retain",
+            "extract",
+        ] {
+            let written_key = written.lines().any(|line| {
+                line.split(" = ").next() == Some(key) || line == ["[", key, "]"].concat()
+            });
+            assert!(!written_key, "{} was written:\n{}", key, written);
+        }
+
+        let rewritten: Config = toml::from_str(&written).unwrap();
+        assert!(rewritten.by_hash.enabled);
+        assert_eq!(rewritten.metapackage.unwrap()[0].section, "metapackages");
+    }
+}
@@ -0,0 +1,105 @@
+use chrono::Duration;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+/// Optional fields which will be written into the Release files of a suite.
+#[derive(Debug, Default, Deserialize, Clone, PartialEq, Serialize)]
+pub struct ReleaseFields {
+    /// How long clients should consider the Release file valid after it was generated, such
+    /// as `7d` or `1d12h`.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_duration",
+        serialize_with = "serialize_duration",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub valid_until: Option<Duration>,
+    /// Prevents packages from this suite from being installed automatically.
+    #[serde(default, skip_serializing_if = "super::is_default")]
+    pub not_automatic: bool,
+    /// Allows upgrades to packages that were installed from this suite, with `not_automatic`.
+    #[serde(default, skip_serializing_if = "super::is_default")]
+    pub but_automatic_upgrades: bool,
+    /// Fingerprints of the keys which the suite is expected to be signed by.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signed_by: Vec<String>,
+    /// A URL template for fetching changelogs, such as `https://example.com/@CHANGEPATH@`.
+    pub changelogs: Option<String>,
+}
+
+/// Parses a duration written as a sequence of integers with `w`, `d`, `h`, `m`, or `s` units.
+/// Durations which are too long to be represented are rejected.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+
+    for character in input.trim().chars() {
+        if character.is_ascii_digit() {
+            digits.push(character);
+            continue;
+        }
+
+        let value = digits.parse::<i64>().ok()?;
+        digits.clear();
+        let unit = match character {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+
+        // `Duration::seconds` panics beyond the maximum duration.
+        let seconds = value
+            .checked_mul(unit)
+            .filter(|&seconds| seconds <= Duration::max_value().num_seconds())?;
+        total = total.checked_add(&Duration::seconds(seconds))?;
+    }
+
+    if digits.is_empty() && !total.is_zero() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let input = String::deserialize(deserializer)?;
+    parse_duration(&input)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid duration: {}", input)))
+}
+
+fn serialize_duration<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_str(&format!("{}s", duration.num_seconds())),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("7d"), Some(Duration::days(7)));
+        assert_eq!(
+            parse_duration("1d12h"),
+            Some(Duration::days(1) + Duration::hours(12))
+        );
+        assert_eq!(parse_duration("90"), None);
+        assert_eq!(parse_duration("0d"), None);
+        assert_eq!(parse_duration("3y"), None);
+        assert_eq!(parse_duration("9223372036854775807w"), None);
+        assert_eq!(parse_duration("9223372036854776s"), None);
+        assert_eq!(parse_duration("9223372036854775s1s"), None);
+    }
+}
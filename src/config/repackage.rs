@@ -0,0 +1,108 @@
+use crate::debian::control::Fields;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Changes which are made to a direct package when it is repackaged, in addition to the files
+/// in its replacement tree.
+#[derive(Debug, Default, Deserialize, Clone, Serialize)]
+pub struct Repackage {
+    /// Control fields to define, replacing their values if they are already defined.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+    /// Relationships to append to control fields, such as `Depends`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub append: BTreeMap<String, String>,
+    /// Control fields to remove.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+    /// Appended to the version of the package, such as `pop1`.
+    pub version_suffix: Option<String>,
+    /// Glob patterns of files in the data archive to remove, such as `usr/share/doc/**`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove_files: Vec<String>,
+}
+
+impl Repackage {
+    /// Applies the changes to the fields of the control file.
+    pub fn apply(&self, control: &mut Fields) {
+        control.retain(|(key, _)| !self.remove.iter().any(|k| k.eq_ignore_ascii_case(key)));
+
+        for (key, value) in &self.set {
+            set_field(control, key, value.clone());
+        }
+
+        for (key, value) in &self.append {
+            let value = match position(control, key) {
+                Some(pos) if !control[pos].1.trim().is_empty() => {
+                    [control[pos].1.trim_end(), ", ", value].concat()
+                }
+                _ => value.clone(),
+            };
+
+            set_field(control, key, value);
+        }
+
+        if let Some(ref suffix) = self.version_suffix
+            && let Some(pos) = position(control, "Version")
+        {
+            control[pos].1.push_str(suffix);
+        }
+    }
+}
+
+fn position(control: &Fields, key: &str) -> Option<usize> {
+    control
+        .iter()
+        .position(|(k, _)| k.eq_ignore_ascii_case(key))
+}
+
+/// Replaces the value of a field, or adds it before the description.
+fn set_field(control: &mut Fields, key: &str, value: String) {
+    match position(control, key) {
+        Some(pos) => control[pos].1 = value,
+        None => {
+            let pos = position(control, "Description").unwrap_or(control.len());
+            control.insert(pos, (key.to_owned(), value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debian::control;
+
+    #[test]
+    fn repackage_control() {
+        let repackage: Repackage = toml::from_str(
+            r#"
+            remove = ["recommends"]
+            version_suffix = "pop1"
+
+            [set]
+            Maintainer = "System76 <info@system76.com>"
+            Section = "utils"
+
+            [append]
+            Depends = "libfoo (>= 1.0)"
+            Breaks = "bar (<< 2.0)"
+            "#,
+        )
+        .unwrap();
+
+        let mut fields = control::parse(
+            "Package: foo\nVersion: 1.0\nMaintainer: Upstream <a@b.c>\nDepends: libc6\n\
+             Recommends: baz\nDescription: summary\n long description\n",
+        );
+
+        repackage.apply(&mut fields);
+        assert_eq!(
+            fields,
+            control::parse(
+                "Package: foo\nVersion: 1.0pop1\nMaintainer: System76 <info@system76.com>\n\
+                 Depends: libc6, libfoo (>= 1.0)\nSection: utils\nBreaks: bar (<< 2.0)\n\
+                 Description: summary\n long description\n"
+            )
+        );
+    }
+}
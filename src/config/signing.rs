@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The program which will sign the `InRelease` and `Release.gpg` files.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningBackend {
+    /// Signs with the `gpg` binary, using the key in the keyring that matches the suite's email.
+    Gpg,
+    /// Signs in-process, with the secret keys defined in the suite's config.
+    Native,
+}
+
+/// Where an armored OpenPGP secret key can be read from.
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum KeyLocation {
+    /// Reads the key from a file, relative to the directory of the suite's config.
+    Path { path: PathBuf },
+    /// Reads the key from an environment variable.
+    Env { env: String },
+}
+
+/// A secret key which the release files will be signed with.
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub struct SigningKey {
+    #[serde(flatten)]
+    pub location: KeyLocation,
+    /// The environment variable that stores the key's password, if it has one.
+    pub password_env: Option<String>,
+}
+
+/// Controls how the release files of a suite will be signed.
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub struct Signing {
+    #[serde(
+        default = "default_backend",
+        skip_serializing_if = "is_default_backend"
+    )]
+    pub backend: SigningBackend,
+    /// Keys for the native backend. Each key adds its own signature to the release files,
+    /// so that an old and a new key may both sign the suite while clients migrate.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keys: Vec<SigningKey>,
+}
+
+impl Default for Signing {
+    fn default() -> Self {
+        Signing {
+            backend: default_backend(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+fn default_backend() -> SigningBackend {
+    SigningBackend::Gpg
+}
+fn is_default_backend(backend: &SigningBackend) -> bool {
+    *backend == default_backend()
+}
@@ -1,3 +1,4 @@
+use super::ChecksumsFile;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -22,8 +23,13 @@ pub enum DebianPath {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum SourceLocation {
-    /// Fetch the source from a remote tarball.
-    URL { url: String, checksum: String },
+    /// Fetch the source from a remote tarball, verified with a checksum, or with the checksum
+    /// that is looked up in a remote checksums file.
+    URL {
+        url: String,
+        checksum: Option<String>,
+        checksums: Option<ChecksumsFile>,
+    },
     /// Fetch the source by the git repository where it can be reached.
     Git {
         git: String,
@@ -44,13 +50,21 @@ pub struct Source {
     pub prebuild: Option<Vec<String>>,
     pub build_on: Option<String>,
     pub repos: Option<Vec<String>>,
-    #[serde(default = "default_build_source")]
+    #[serde(
+        default = "default_build_source",
+        skip_serializing_if = "is_default_build_source"
+    )]
     pub keep_source: bool,
     pub debian: Option<DebianPath>,
     pub depends: Option<Vec<String>>,
-    #[serde(default = "default_retain")]
+    #[serde(default = "default_retain", skip_serializing_if = "is_default_retain")]
     pub retain: usize,
-    #[serde(default = "default_requires_extract")]
+    /// How many of the newest versions of this package in the pool will be indexed.
+    pub index_versions: Option<usize>,
+    #[serde(
+        default = "default_requires_extract",
+        skip_serializing_if = "is_default_requires_extract"
+    )]
     pub extract: bool,
 }
 
@@ -63,3 +77,12 @@ fn default_retain() -> usize {
 fn default_requires_extract() -> bool {
     true
 }
+fn is_default_build_source(keep: &bool) -> bool {
+    *keep == default_build_source()
+}
+fn is_default_retain(retain: &usize) -> bool {
+    *retain == default_retain()
+}
+fn is_default_requires_extract(extract: &bool) -> bool {
+    *extract == default_requires_extract()
+}
@@ -0,0 +1,131 @@
+//! The architectures which a suite publishes binary packages for.
+
+use serde::{Deserialize, Serialize};
+
+/// The architecture of packages which may be installed on any architecture.
+pub const ALL: &str = "all";
+
+const BINARY_PREFIX: &str = "binary-";
+
+/// Gets the name of the directory in the pool and dists which stores binaries of an architecture.
+pub fn binary_dir(arch: &str) -> String {
+    [BINARY_PREFIX, arch].concat()
+}
+
+/// Gets the architecture of a binary directory in the pool or dists, if it is one.
+pub fn from_binary_dir(directory: &str) -> Option<&str> {
+    directory
+        .strip_prefix(BINARY_PREFIX)
+        .filter(|arch| !arch.is_empty())
+}
+
+/// Where packages of the `all` architecture are indexed in the dists.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchAll {
+    /// Only in the `binary-all` Packages and `Contents-all` indices.
+    #[default]
+    Separate,
+    /// Also in the Packages and Contents indices of every other architecture, for clients
+    /// which do not support `binary-all`. The Release will declare that `binary-all` is not
+    /// needed by clients which do.
+    Merged,
+}
+
+/// The architectures defined in the `architectures` field of a suite's config.
+#[derive(Clone, Debug)]
+pub struct Architectures {
+    architectures: Vec<String>,
+    /// Whether `all` was among the configured architectures.
+    all: bool,
+}
+
+impl Architectures {
+    /// Collects the configured architectures, in their original order and without duplicates.
+    /// `all` is always supported, so it is not stored among them.
+    pub fn new(configured: &[String]) -> Self {
+        let mut architectures: Vec<String> = Vec::with_capacity(configured.len());
+        let mut all = false;
+        for arch in configured {
+            let arch = arch.trim();
+            if arch == ALL {
+                all = true;
+            } else if !arch.is_empty() && !architectures.iter().any(|a| a == arch) {
+                architectures.push(arch.to_owned());
+            }
+        }
+
+        Architectures { architectures, all }
+    }
+
+    /// The architectures which packages may be built for, excluding `all`.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.architectures.iter().map(String::as_str)
+    }
+
+    /// The architectures which sources are built for, including `all` only if it was configured.
+    pub fn configured(&self) -> impl Iterator<Item = &str> {
+        self.iter().chain(self.all.then_some(ALL))
+    }
+
+    /// Every architecture which binaries may be published for, including `all`.
+    pub fn with_all(&self) -> impl Iterator<Item = &str> {
+        self.iter().chain(Some(ALL))
+    }
+
+    /// Whether binaries of this architecture are published by the suite.
+    pub fn contains(&self, arch: &str) -> bool {
+        arch == ALL || self.architectures.iter().any(|a| a == arch)
+    }
+
+    /// Gets the architecture from the stem of a file name, such as `package_1.0.0_amd64`.
+    pub fn from_stem<'a>(&'a self, stem: &'a str) -> &'a str {
+        let suffixed = |arch: &str| {
+            stem.strip_suffix(arch)
+                .is_some_and(|rest| rest.ends_with('_') || rest.ends_with('-'))
+        };
+
+        if let Some(arch) = self.with_all().find(|arch| suffixed(arch)) {
+            return arch;
+        }
+
+        let arch = &stem[stem.rfind('_').map_or(0, |pos| pos + 1)..];
+        arch.find('-').map_or(arch, |pos| &arch[..pos])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_architectures() {
+        let configured = ["amd64", "riscv64", "all", "amd64"].map(String::from);
+        let architectures = Architectures::new(&configured);
+        assert_eq!(
+            architectures.with_all().collect::<Vec<_>>(),
+            vec!["amd64", "riscv64", "all"]
+        );
+        assert_eq!(
+            architectures.configured().collect::<Vec<_>>(),
+            vec!["amd64", "riscv64", "all"]
+        );
+        assert!(architectures.contains("all"));
+
+        let without_all = Architectures::new(&["amd64".into()]);
+        assert_eq!(without_all.configured().collect::<Vec<_>>(), vec!["amd64"]);
+        assert!(!architectures.contains("i386"));
+
+        assert_eq!(
+            architectures.from_stem("system76-power_1.0.0_riscv64"),
+            "riscv64"
+        );
+        assert_eq!(architectures.from_stem("firmware-install_1.0.0_all"), "all");
+        assert_eq!(architectures.from_stem("steam-launcher-amd64"), "amd64");
+        assert_eq!(architectures.from_stem("package_1.0.0_loong64"), "loong64");
+
+        assert_eq!(binary_dir("riscv64"), "binary-riscv64");
+        assert_eq!(from_binary_dir("binary-loong64"), Some("loong64"));
+        assert_eq!(from_binary_dir("source"), None);
+    }
+}
@@ -0,0 +1,31 @@
+mod writer;
+
+pub use self::writer::*;
+
+use crate::debian::control::{self, Fields};
+use debarchive::Archive as DebArchive;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Reads every field of the `control` file in the control archive of a deb, in their original
+/// order, and with multi-line values intact.
+pub fn control_fields(archive: &DebArchive) -> io::Result<Fields> {
+    let mut fields = None;
+    archive.control(|entry| {
+        let path = entry.path()?;
+        if path == Path::new("./control") || path == Path::new("control") {
+            let mut data = String::new();
+            entry.read_to_string(&mut data)?;
+            fields = Some(control::parse(&data));
+        }
+
+        Ok(())
+    })?;
+
+    fields.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "control file not found in control archive",
+        )
+    })
+}
@@ -0,0 +1,767 @@
+//! Assembles deb archives in-process, so that `dpkg-deb` is not required on the host.
+
+use crate::debian::control::{self, Fields};
+use deflate::Compression;
+use deflate::write::GzEncoder;
+use digest::Digest;
+use hex_view::HexView;
+use libflate::gzip::Decoder as GzDecoder;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tar::EntryType;
+use walkdir::WalkDir;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+/// The compression of the control and data archives within a deb.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebCompression {
+    None,
+    Gz,
+    #[default]
+    Xz,
+    Zstd,
+}
+
+impl DebCompression {
+    fn extension(self) -> &'static str {
+        match self {
+            DebCompression::None => "",
+            DebCompression::Gz => ".gz",
+            DebCompression::Xz => ".xz",
+            DebCompression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Changes to make to the files of a deb when it is repackaged.
+#[derive(Default)]
+pub struct Overrides<'a> {
+    /// Files which replace, or are added to, the data archive.
+    pub data: Option<&'a Path>,
+    /// Files which replace, or are added to, the control archive.
+    pub control: Option<&'a Path>,
+    /// Files of the data archive to remove. A directory which matches is removed with its contents.
+    pub exclude: &'a [glob::Pattern],
+}
+
+/// Writes a new deb to `dst` from the deb at `source`, with the overrides applied.
+///
+/// The data archive of the source is streamed into the new deb, rather than being extracted.
+/// Entries keep their order in the source, files which are added are written after them in
+/// sorted order, and every timestamp is set to `SOURCE_DATE_EPOCH`, so that repackaging the
+/// same deb twice will produce the same file. Installed-Size and md5sums are updated to match
+/// the new data archive, before the control fields are given to `patch`.
+///
+/// Hard links to files which are removed are rejected, as they could not be extracted.
+pub fn repackage<F>(
+    source: &Path,
+    dst: &Path,
+    overrides: &Overrides,
+    compression: DebCompression,
+    patch: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&mut Fields),
+{
+    let mtime = source_date_epoch();
+
+    let mut control_files = BTreeMap::new();
+    inner_archive(source, "control.tar", |archive| {
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.header().entry_type().is_file() {
+                let path = relative(&entry.path_bytes());
+                let mode = entry.header().mode()?;
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data)?;
+                control_files.insert(path, (mode, data));
+            }
+        }
+
+        Ok(())
+    })?;
+
+    if let Some(dir) = overrides.control {
+        for (path, file) in walk(dir) {
+            let mode = fs::metadata(&file)?.mode() & 0o7777;
+            control_files.insert(path, (mode, fs::read(&file)?));
+        }
+    }
+
+    // Files from the overrides replace the entries of the source archive in place, and any
+    // which remain are added to the end of the archive.
+    let mut added: HashMap<PathBuf, PathBuf> = HashMap::new();
+    if let Some(dir) = overrides.data {
+        added.extend(walk(dir).filter(|(path, _)| !is_excluded(path, overrides.exclude)));
+    }
+
+    let mut files = HashSet::new();
+    let mut data = DataWriter::new(tempfile::tempfile()?, compression, mtime)?;
+    inner_archive(source, "data.tar", |archive| {
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = relative(&entry.path_bytes());
+            if is_excluded(&path, overrides.exclude) {
+                continue;
+            }
+
+            if let Some(file) = added.remove(&path) {
+                data.append_path(&path, &file)?;
+            } else {
+                let metadata = Metadata::from_entry(&entry)?;
+                // The target of a hard link is always written before the link.
+                if metadata.kind == EntryType::Link
+                    && let Some(ref target) = metadata.link
+                    && is_excluded(&relative(target), overrides.exclude)
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{} is a hard link to {}, which is removed",
+                            path.display(),
+                            String::from_utf8_lossy(target)
+                        ),
+                    ));
+                }
+
+                data.append(&path, &metadata, &mut entry)?;
+            }
+
+            files.insert(path);
+        }
+
+        Ok(())
+    })?;
+
+    let mut added: Vec<(PathBuf, PathBuf)> = added.into_iter().collect();
+    added.sort_unstable_by(|(a, _), (b, _)| a.as_os_str().as_bytes().cmp(b.as_os_str().as_bytes()));
+    for (path, file) in added {
+        data.append_path(&path, &file)?;
+        files.insert(path);
+    }
+
+    let (data, installed_size, md5sums) = data.finish()?;
+
+    let conffiles = control_files
+        .get(Path::new("conffiles"))
+        .map(|(_, conffiles)| String::from_utf8_lossy(conffiles).into_owned())
+        .unwrap_or_default();
+
+    if let Some((mode, conffiles)) = control_files.remove(Path::new("conffiles")) {
+        let mut output = Vec::with_capacity(conffiles.len());
+        for line in conffiles.split(|&byte| byte == b'\n') {
+            // The path is the last field, after an optional flag.
+            let path = line.rsplit(|&byte| byte == b' ').next().unwrap_or(line);
+            if !line.is_empty() && files.contains(&relative(path)) {
+                output.extend_from_slice(line);
+                output.push(b'\n');
+            }
+        }
+
+        control_files.insert(PathBuf::from("conffiles"), (mode, output));
+    }
+
+    // Conffiles are not listed in the md5sums, as dpkg records their checksums separately.
+    if let Some((_, output)) = control_files.get_mut(Path::new("md5sums")) {
+        let conffiles: Vec<PathBuf> = conffiles
+            .lines()
+            .filter_map(|line| line.rsplit(' ').next())
+            .map(|path| relative(path.as_bytes()))
+            .collect();
+
+        output.clear();
+        for (path, md5) in md5sums.iter().filter(|(path, _)| !conffiles.contains(path)) {
+            output.extend_from_slice(md5.as_bytes());
+            output.extend_from_slice(b"  ");
+            output.extend_from_slice(path.as_os_str().as_bytes());
+            output.push(b'\n');
+        }
+    }
+
+    let (_, control_file) = control_files
+        .get_mut(Path::new("control"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "control file not found"))?;
+
+    let mut fields = control::parse(&String::from_utf8_lossy(control_file));
+    if (overrides.data.is_some() || !overrides.exclude.is_empty())
+        && let Some((_, size)) = fields
+            .iter_mut()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Installed-Size"))
+    {
+        *size = installed_size.to_string();
+    }
+
+    patch(&mut fields);
+
+    control_file.clear();
+    for (key, value) in &fields {
+        control::write_field(control_file, key.as_bytes(), value.as_bytes());
+    }
+
+    write_deb(dst, &control_files, data, compression, mtime)
+}
+
+/// Writes a new deb to `dst` which contains no files, such as a metapackage.
+pub fn build(dst: &Path, fields: &Fields, compression: DebCompression) -> io::Result<()> {
+    let mtime = source_date_epoch();
+
+    let mut data = DataWriter::new(tempfile::tempfile()?, compression, mtime)?;
+    let root = Metadata::root(EntryType::Directory, 0o755, 0);
+    data.append(Path::new(""), &root, io::empty())?;
+    let (data, installed_size, _) = data.finish()?;
+
+    // The Installed-Size is declared after the Architecture, where `dpkg-gencontrol` places it.
+    let mut control = Vec::with_capacity(1024);
+    for (key, value) in fields {
+        control::write_field(&mut control, key.as_bytes(), value.as_bytes());
+        if key.eq_ignore_ascii_case("Architecture") {
+            let installed_size = installed_size.to_string();
+            control::write_field(&mut control, b"Installed-Size", installed_size.as_bytes());
+        }
+    }
+
+    let control_files = BTreeMap::from([(PathBuf::from("control"), (0o644, control))]);
+    write_deb(dst, &control_files, data, compression, mtime)
+}
+
+/// Writes the ar archive of a deb, with the control files and the compressed data archive.
+fn write_deb(
+    dst: &Path,
+    control_files: &BTreeMap<PathBuf, (u32, Vec<u8>)>,
+    mut data: File,
+    compression: DebCompression,
+    mtime: u64,
+) -> io::Result<()> {
+    let mut control = tar::Builder::new(Encoder::new(Vec::new(), compression)?);
+    let root = Metadata::root(EntryType::Directory, 0o755, 0);
+    append(&mut control, Path::new(""), &root, mtime, io::empty())?;
+    for (path, (mode, contents)) in control_files {
+        let metadata = Metadata::root(EntryType::Regular, *mode, contents.len() as u64);
+        append(&mut control, path, &metadata, mtime, contents.as_slice())?;
+    }
+
+    let control = control.into_inner()?.finish()?;
+
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let extension = compression.extension();
+    let mut deb = ar::Builder::new(File::create(dst)?);
+    let mut member = |name: String, size: u64, contents: &mut dyn Read| {
+        let mut header = ar::Header::new(name.into_bytes(), size);
+        header.set_mtime(mtime);
+        header.set_mode(0o100644);
+        deb.append(&header, contents)
+    };
+
+    member("debian-binary".into(), 4, &mut &b"2.0\n"[..])?;
+    member(
+        ["control.tar", extension].concat(),
+        control.len() as u64,
+        &mut control.as_slice(),
+    )?;
+
+    let size = data.seek(SeekFrom::End(0))?;
+    data.seek(SeekFrom::Start(0))?;
+    member(["data.tar", extension].concat(), size, &mut data)
+}
+
+/// Opens the inner tar archive whose name begins with `member` in a deb.
+fn inner_archive<T, F>(deb: &Path, member: &str, func: F) -> io::Result<T>
+where
+    F: FnOnce(&mut tar::Archive<&mut dyn Read>) -> io::Result<T>,
+{
+    let mut archive = ar::Archive::new(File::open(deb)?);
+    while let Some(entry) = archive.next_entry() {
+        let entry = entry?;
+        let identifier = entry.header().identifier().to_owned();
+        let Some(extension) = identifier.strip_prefix(member.as_bytes()) else {
+            continue;
+        };
+
+        let mut reader: Box<dyn Read> = match extension {
+            b"" => Box::new(entry),
+            b".gz" => Box::new(GzDecoder::new(entry)?),
+            b".xz" => Box::new(XzDecoder::new(entry)),
+            b".zst" => Box::new(zstd::Decoder::new(entry)?),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "unsupported compression of {} in {}",
+                        String::from_utf8_lossy(&identifier),
+                        deb.display()
+                    ),
+                ));
+            }
+        };
+
+        return func(&mut tar::Archive::new(reader.as_mut()));
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} archive not found in {}", member, deb.display()),
+    ))
+}
+
+/// Converts a path in a tar archive to a path relative to its root, without a leading `./`.
+fn relative(path: &[u8]) -> PathBuf {
+    Path::new(OsStr::from_bytes(path))
+        .components()
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .collect()
+}
+
+fn is_excluded(path: &Path, exclude: &[glob::Pattern]) -> bool {
+    path.ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .any(|path| exclude.iter().any(|pattern| pattern.matches_path(path)))
+}
+
+/// Every file and directory within `dir`, relative to `dir`.
+fn walk(dir: &Path) -> impl Iterator<Item = (PathBuf, PathBuf)> + '_ {
+    WalkDir::new(dir)
+        .min_depth(1)
+        .into_iter()
+        .flat_map(|e| e.ok())
+        .map(move |entry| {
+            let path = entry.path().strip_prefix(dir).unwrap().to_path_buf();
+            (path, entry.into_path())
+        })
+}
+
+fn source_date_epoch() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or(0)
+}
+
+/// The fields of a tar header which are preserved in the new archive.
+struct Metadata {
+    kind: EntryType,
+    mode: u32,
+    uid: u64,
+    gid: u64,
+    user: String,
+    group: String,
+    size: u64,
+    link: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// A file or directory which is owned by root.
+    fn root(kind: EntryType, mode: u32, size: u64) -> Self {
+        Metadata {
+            kind,
+            mode,
+            uid: 0,
+            gid: 0,
+            user: "root".into(),
+            group: "root".into(),
+            size,
+            link: None,
+        }
+    }
+
+    fn from_entry<R: Read>(entry: &tar::Entry<R>) -> io::Result<Self> {
+        let header = entry.header();
+        let name = |name: Option<&[u8]>| String::from_utf8_lossy(name.unwrap_or_default()).into();
+        Ok(Metadata {
+            kind: header.entry_type(),
+            mode: header.mode()?,
+            uid: header.uid()?,
+            gid: header.gid()?,
+            user: name(header.username_bytes()),
+            group: name(header.groupname_bytes()),
+            size: entry.size(),
+            link: entry.link_name_bytes().map(|link| link.into_owned()),
+        })
+    }
+
+    /// Files which are added from the disk are owned by root, like `dpkg-deb --root-owner-group`.
+    fn from_path(path: &Path) -> io::Result<Self> {
+        let metadata = fs::symlink_metadata(path)?;
+        let mode = metadata.mode() & 0o7777;
+        Ok(if metadata.is_dir() {
+            Metadata::root(EntryType::Directory, mode, 0)
+        } else if metadata.file_type().is_symlink() {
+            let mut link = Metadata::root(EntryType::Symlink, mode, 0);
+            link.link = Some(fs::read_link(path)?.into_os_string().into_vec());
+            link
+        } else {
+            Metadata::root(EntryType::Regular, mode, metadata.len())
+        })
+    }
+}
+
+/// The md5sum of each file in the data archive, in the order that they were written.
+type Md5Sums = Vec<(PathBuf, String)>;
+
+/// Writes the data archive, while keeping track of the installed size and md5sums of its files.
+struct DataWriter {
+    builder: tar::Builder<Encoder<File>>,
+    mtime: u64,
+    installed_size: u64,
+    md5sums: Md5Sums,
+}
+
+impl DataWriter {
+    fn new(file: File, compression: DebCompression, mtime: u64) -> io::Result<Self> {
+        Ok(DataWriter {
+            builder: tar::Builder::new(Encoder::new(file, compression)?),
+            mtime,
+            installed_size: 0,
+            md5sums: Vec::new(),
+        })
+    }
+
+    fn append<R: Read>(&mut self, path: &Path, metadata: &Metadata, data: R) -> io::Result<()> {
+        // Sizes are counted in KiB, as `dpkg-gencontrol` would count them.
+        if metadata.kind.is_file() {
+            self.installed_size += metadata.size.div_ceil(1024);
+            let mut reader = Md5Reader {
+                inner: data,
+                hasher: Md5::new(),
+            };
+
+            append(&mut self.builder, path, metadata, self.mtime, &mut reader)?;
+            let md5 = format!("{:x}", HexView::from(reader.hasher.finalize().as_slice()));
+            self.md5sums.push((path.to_path_buf(), md5));
+            Ok(())
+        } else {
+            self.installed_size += 1;
+            append(&mut self.builder, path, metadata, self.mtime, data)
+        }
+    }
+
+    /// Appends a file or directory from the disk.
+    fn append_path(&mut self, path: &Path, file: &Path) -> io::Result<()> {
+        let metadata = Metadata::from_path(file)?;
+        if metadata.kind.is_file() {
+            self.append(path, &metadata, File::open(file)?)
+        } else {
+            self.append(path, &metadata, io::empty())
+        }
+    }
+
+    fn finish(self) -> io::Result<(File, u64, Md5Sums)> {
+        let file = self.builder.into_inner()?.finish()?;
+        Ok((file, self.installed_size, self.md5sums))
+    }
+}
+
+struct Md5Reader<R> {
+    inner: R,
+    hasher: Md5,
+}
+
+impl<R: Read> Read for Md5Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Appends an entry with a path in the format that `dpkg-deb` writes: beginning with `./`,
+/// and with a trailing `/` for directories. Paths and links which are too long for the header
+/// are stored in GNU long name entries.
+fn append<W: Write, R: Read>(
+    builder: &mut tar::Builder<W>,
+    path: &Path,
+    metadata: &Metadata,
+    mtime: u64,
+    data: R,
+) -> io::Result<()> {
+    let mut name = b"./".to_vec();
+    if !path.as_os_str().is_empty() {
+        name.extend_from_slice(path.as_os_str().as_bytes());
+        if metadata.kind.is_dir() {
+            name.push(b'/');
+        }
+    }
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(metadata.kind);
+    header.set_mode(metadata.mode);
+    header.set_uid(metadata.uid);
+    header.set_gid(metadata.gid);
+    header.set_username(&metadata.user)?;
+    header.set_groupname(&metadata.group)?;
+    header.set_mtime(mtime);
+    header.set_size(if metadata.kind.is_file() {
+        metadata.size
+    } else {
+        0
+    });
+
+    if let Some(ref link) = metadata.link {
+        let linkname = &mut header.as_old_mut().linkname;
+        if link.len() > linkname.len() {
+            long_name(builder, b'K', link)?;
+        }
+
+        let length = link.len().min(linkname.len());
+        linkname[..length].copy_from_slice(&link[..length]);
+    }
+
+    let slot = &mut header.as_old_mut().name;
+    if name.len() > slot.len() {
+        long_name(builder, b'L', &name)?;
+    }
+
+    let length = name.len().min(slot.len());
+    slot[..length].copy_from_slice(&name[..length]);
+
+    header.set_cksum();
+    builder.append(&header, data)
+}
+
+fn long_name<W: Write>(builder: &mut tar::Builder<W>, kind: u8, name: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.as_old_mut().name[..13].copy_from_slice(b"././@LongLink");
+    header.set_entry_type(EntryType::new(kind));
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_size(name.len() as u64 + 1);
+    header.set_cksum();
+    builder.append(&header, name.chain(&[0][..]))
+}
+
+/// Compresses an inner archive of a deb, which must be finished to write its trailer.
+enum Encoder<W: Write> {
+    None(W),
+    Gz(Box<GzEncoder<W>>),
+    Xz(XzEncoder<W>),
+    Zstd(ZstdEncoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Uses the same compression levels as `dpkg-deb` by default.
+    fn new(writer: W, compression: DebCompression) -> io::Result<Self> {
+        Ok(match compression {
+            DebCompression::None => Encoder::None(writer),
+            DebCompression::Gz => Encoder::Gz(Box::new(GzEncoder::new(writer, Compression::Best))),
+            DebCompression::Xz => Encoder::Xz(XzEncoder::new(writer, 6)),
+            DebCompression::Zstd => Encoder::Zstd(ZstdEncoder::new(writer, 3)?),
+        })
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::None(writer) => Ok(writer),
+            Encoder::Gz(encoder) => encoder.finish(),
+            Encoder::Xz(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::None(writer) => writer.write(buf),
+            Encoder::Gz(encoder) => encoder.write(buf),
+            Encoder::Xz(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::None(writer) => writer.flush(),
+            Encoder::Gz(encoder) => encoder.flush(),
+            Encoder::Xz(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tar::EntryType::{Directory, Link, Regular};
+
+    /// Builds a tar archive of the entries, where the contents of a hard link are its target.
+    fn tar(entries: &[(&str, EntryType, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for &(path, kind, contents) in entries {
+            let mut metadata = match kind {
+                Directory => Metadata::root(kind, 0o755, 0),
+                Link => Metadata::root(kind, 0o644, 0),
+                _ => Metadata::root(kind, 0o644, contents.len() as u64),
+            };
+
+            let mut data = contents;
+            if kind == Link {
+                metadata.link = Some(contents.to_vec());
+                data = b"";
+            }
+
+            append(&mut builder, &relative(path.as_bytes()), &metadata, 0, data).unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    fn deb(path: &Path, data: &[u8]) {
+        let control = tar(&[
+            (
+                "./control",
+                Regular,
+                b"Package: a\nVersion: 1\nInstalled-Size: 9\nDescription: a\n",
+            ),
+            ("./md5sums", Regular, b""),
+        ]);
+
+        let mut deb = ar::Builder::new(File::create(path).unwrap());
+        for (name, contents) in [
+            ("debian-binary", &b"2.0\n"[..]),
+            ("control.tar", &control),
+            ("data.tar", data),
+        ] {
+            let header = ar::Header::new(name.as_bytes().to_vec(), contents.len() as u64);
+            deb.append(&header, contents).unwrap();
+        }
+    }
+
+    fn data_paths(deb: &Path) -> Vec<String> {
+        let mut paths = Vec::new();
+        inner_archive(deb, "data.tar", |archive| {
+            for entry in archive.entries()? {
+                paths.push(String::from_utf8(entry?.path_bytes().into_owned()).unwrap());
+            }
+
+            Ok(())
+        })
+        .unwrap();
+
+        paths
+    }
+
+    #[test]
+    fn repackage_deb() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, dst) = (dir.path().join("source.deb"), dir.path().join("dst.deb"));
+
+        deb(
+            &source,
+            &tar(&[
+                ("./", Directory, b""),
+                ("./usr/", Directory, b""),
+                ("./usr/share/", Directory, b""),
+                ("./usr/share/doc/", Directory, b""),
+                ("./usr/share/doc/a/", Directory, b""),
+                ("./usr/share/doc/a/copyright", Regular, b"copyright"),
+                ("./usr/bin/", Directory, b""),
+                ("./usr/bin/a", Regular, b"binary"),
+            ]),
+        );
+
+        let exclude = [glob::Pattern::new("usr/share/doc").unwrap()];
+        let overrides = Overrides {
+            exclude: &exclude,
+            ..Overrides::default()
+        };
+
+        repackage(&source, &dst, &overrides, DebCompression::Zstd, |fields| {
+            fields.push(("Section".into(), "utils".into()))
+        })
+        .unwrap();
+
+        assert_eq!(
+            data_paths(&dst),
+            ["./", "./usr/", "./usr/share/", "./usr/bin/", "./usr/bin/a"]
+        );
+
+        let archive = debarchive::Archive::new(&dst).unwrap();
+        let mut md5sums = String::new();
+        archive
+            .control(|entry| {
+                if entry.path()?.ends_with("md5sums") {
+                    entry.read_to_string(&mut md5sums)?;
+                }
+
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(md5sums, "9d7183f16acce70658f686ae7f1a4d20  usr/bin/a\n");
+        assert_eq!(
+            super::super::control_fields(&archive).unwrap(),
+            control::parse(
+                "Package: a\nVersion: 1\nInstalled-Size: 5\nDescription: a\nSection: utils\n"
+            )
+        );
+    }
+
+    #[test]
+    fn repackage_deb_with_overrides_and_hard_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, dst) = (dir.path().join("source.deb"), dir.path().join("dst.deb"));
+
+        deb(
+            &source,
+            &tar(&[
+                ("./", Directory, b""),
+                ("./usr/", Directory, b""),
+                ("./usr/bin/", Directory, b""),
+                ("./usr/bin/b", Regular, b"b"),
+                ("./usr/bin/a", Regular, b"a"),
+                ("./usr/bin/c", Link, b"./usr/bin/b"),
+            ]),
+        );
+
+        let replace = dir.path().join("replace");
+        fs::create_dir_all(replace.join("usr/bin")).unwrap();
+        fs::create_dir_all(replace.join("usr/lib")).unwrap();
+        fs::write(replace.join("usr/bin/a"), "replaced").unwrap();
+        fs::write(replace.join("usr/lib/d"), "added").unwrap();
+
+        let overrides = Overrides {
+            data: Some(&replace),
+            ..Overrides::default()
+        };
+
+        repackage(&source, &dst, &overrides, DebCompression::Xz, |_| ()).unwrap();
+        assert_eq!(
+            data_paths(&dst),
+            [
+                "./",
+                "./usr/",
+                "./usr/bin/",
+                "./usr/bin/b",
+                "./usr/bin/a",
+                "./usr/bin/c",
+                "./usr/lib/",
+                "./usr/lib/d"
+            ]
+        );
+
+        let exclude = [glob::Pattern::new("usr/bin/b").unwrap()];
+        let overrides = Overrides {
+            exclude: &exclude,
+            ..Overrides::default()
+        };
+
+        let why = repackage(&source, &dst, &overrides, DebCompression::Xz, |_| ()).unwrap_err();
+        assert_eq!(why.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,127 @@
+//! Parsing and serialization of Debian control paragraphs, in the deb822 format.
+
+/// The fields of a control paragraph, in the order that they were defined.
+///
+/// Multi-line values are stored with their continuation lines intact, each one preceded by a
+/// newline. A value beginning with a newline therefore has an empty first line.
+pub type Fields = Vec<(String, String)>;
+
+/// Parses the first paragraph of a control file, keeping the original field order.
+pub fn parse(input: &str) -> Fields {
+    let mut fields: Fields = Vec::new();
+
+    for line in input.lines() {
+        if line.trim().is_empty() {
+            if fields.is_empty() {
+                continue;
+            }
+
+            break;
+        } else if line.starts_with('#') {
+            continue;
+        } else if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line);
+            }
+        } else if let Some(pos) = line.find(':') {
+            let (key, value) = line.split_at(pos);
+            fields.push((key.trim().to_owned(), value[1..].trim().to_owned()));
+        }
+    }
+
+    fields
+}
+
+/// Strips the OpenPGP armor from a clearsigned control file, such as a signed `.dsc`.
+pub fn strip_signature(input: &str) -> String {
+    if !input.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+        return input.to_owned();
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut lines = input.lines().skip(1);
+
+    // Skip the armor headers, which are terminated by an empty line.
+    for line in &mut lines {
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+
+    for line in lines {
+        if line.starts_with("-----BEGIN PGP SIGNATURE-----") {
+            break;
+        }
+
+        output.push_str(line.strip_prefix("- ").unwrap_or(line));
+        output.push('\n');
+    }
+
+    output
+}
+
+/// Fetches the value of a field by its key, if it exists.
+pub fn get<'a>(fields: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(field, _)| field.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Serializes a field to the output buffer.
+pub fn write_field(output: &mut Vec<u8>, key: &[u8], value: &[u8]) {
+    output.extend_from_slice(key);
+    output.push(b':');
+    if value.first() != Some(&b'\n') {
+        output.push(b' ');
+    }
+    output.extend_from_slice(value);
+    output.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DSC: &str = r#"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Format: 3.0 (native)
+Source: system76-power
+Binary: system76-power
+Version: 1.0.0
+Files:
+ d41d8cd98f00b204e9800998ecf8427e 0 system76-power_1.0.0.tar.xz
+Checksums-Sha256:
+ e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855 0 system76-power_1.0.0.tar.xz
+
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCgAdFiEE
+-----END PGP SIGNATURE-----
+"#;
+
+    #[test]
+    fn dsc_fields() {
+        let fields = parse(&strip_signature(DSC));
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[1], ("Source".into(), "system76-power".into()));
+        assert_eq!(
+            get(&fields, "Files"),
+            Some("\n d41d8cd98f00b204e9800998ecf8427e 0 system76-power_1.0.0.tar.xz")
+        );
+
+        let mut output = Vec::new();
+        write_field(
+            &mut output,
+            b"Files",
+            get(&fields, "Files").unwrap().as_bytes(),
+        );
+        write_field(&mut output, b"Version", b"1.0.0");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Files:\n d41d8cd98f00b204e9800998ecf8427e 0 system76-power_1.0.0.tar.xz\nVersion: 1.0.0\n"
+        );
+    }
+}
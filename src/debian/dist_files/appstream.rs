@@ -0,0 +1,724 @@
+//! AppStream metadata in the DEP-11 format, which software centers use to find applications.
+
+use super::PackageEntry;
+use crate::compress::{CompressOptions, UNCOMPRESSED, compress};
+use crate::config::Config;
+use crate::debian::arch::{ALL, ArchAll};
+use crate::debian::{Entries, control};
+use debarchive::Archive as DebArchive;
+use deflate::Compression;
+use deflate::write::GzEncoder;
+use itertools::Itertools;
+use rayon::prelude::*;
+use roxmltree::{Document, NS_XML_URI, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+/// The width and height of the icons which are cached in the icon tarball of each component.
+const ICON_SIZE: u32 = 64;
+
+const ICONS_TARBALL: &str = "icons-64x64.tar.gz";
+
+/// The length of the signature and `IHDR` chunk which begin a PNG image.
+const PNG_HEADER: usize = 24;
+
+/// The version of the DEP-11 format which is generated.
+const DEP11_VERSION: &str = "0.14";
+
+/// Files in the data archive of a package which describe its AppStream components.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AppStreamFiles {
+    /// The metainfo files in `usr/share/metainfo`, or the legacy `usr/share/appdata`.
+    pub metainfo: Vec<String>,
+    /// Desktop entries in `usr/share/applications`, by their file name.
+    pub desktop: HashMap<String, String>,
+    /// The paths of PNG icons of 64x64 pixels in the data archive, by their icon name. They are
+    /// only read again when the icon tarball is written.
+    pub icons: HashMap<String, PathBuf>,
+}
+
+impl AppStreamFiles {
+    /// Reads a regular file from the data archive, if it is needed for AppStream metadata.
+    pub fn collect<R: Read>(&mut self, path: &Path, file: &mut R) -> io::Result<()> {
+        let path = path.strip_prefix("./").unwrap_or(path);
+        let (parent, name) = match (
+            path.parent().and_then(Path::to_str),
+            path.file_name().and_then(|name| name.to_str()),
+        ) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => return Ok(()),
+        };
+
+        match parent {
+            "usr/share/metainfo" | "usr/share/appdata" if name.ends_with(".xml") => {
+                self.metainfo.push(read_string(file)?);
+            }
+            "usr/share/applications" if name.ends_with(".desktop") => {
+                self.desktop.insert(name.to_owned(), read_string(file)?);
+            }
+            "usr/share/icons/hicolor/64x64/apps" | "usr/share/pixmaps" => {
+                let icon = match name.strip_suffix(".png") {
+                    Some(icon) => icon,
+                    None => return Ok(()),
+                };
+
+                let mut header = Vec::with_capacity(PNG_HEADER);
+                file.take(PNG_HEADER as u64).read_to_end(&mut header)?;
+                if png_size(&header) != Some((ICON_SIZE, ICON_SIZE)) {
+                    return Ok(());
+                }
+
+                // Icons from the icon theme take precedence over pixmaps.
+                let path = path.to_path_buf();
+                if parent == "usr/share/pixmaps" {
+                    self.icons.entry(icon.to_owned()).or_insert(path);
+                } else {
+                    self.icons.insert(icon.to_owned(), path);
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Parses the components described by the metainfo files of the package. Metainfo files
+    /// which are invalid are skipped with a warning.
+    fn components<'a>(&'a self, package: &'a str, deb: &'a Path) -> Vec<Component<'a>> {
+        let mut components = Vec::new();
+        for metainfo in &self.metainfo {
+            let document = match Document::parse(metainfo) {
+                Ok(document) => document,
+                Err(why) => {
+                    log::warn!("skipping invalid metainfo file in {}: {}", package, why);
+                    continue;
+                }
+            };
+
+            let root = document.root_element();
+            let nodes: Vec<Node> = match root.tag_name().name() {
+                "component" => vec![root],
+                "components" => root
+                    .children()
+                    .filter(|node| node.has_tag_name("component"))
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            for node in nodes {
+                match Component::parse(node, package, deb, self) {
+                    Some(component) => components.push(component),
+                    None => log::warn!(
+                        "skipping metainfo component in {} without an ID or name",
+                        package
+                    ),
+                }
+            }
+        }
+
+        components
+    }
+}
+
+/// An AppStream component, as it will be written to the DEP-11 metadata.
+#[derive(Debug, Default)]
+struct Component<'a> {
+    kind: &'static str,
+    id: String,
+    package: &'a str,
+    name: BTreeMap<String, String>,
+    summary: BTreeMap<String, String>,
+    description: BTreeMap<String, String>,
+    developer_name: BTreeMap<String, String>,
+    project_license: Option<String>,
+    categories: Vec<String>,
+    keywords: Vec<String>,
+    urls: BTreeMap<String, String>,
+    launchables: Vec<String>,
+    stock_icon: Option<String>,
+    cached_icon: Option<CachedIcon<'a>>,
+}
+
+/// An icon which will be written to the icon tarball.
+#[derive(Debug)]
+struct CachedIcon<'a> {
+    /// The name of the icon in the icon tarball.
+    name: String,
+    /// The deb which contains the icon.
+    deb: &'a Path,
+    /// The path of the icon in the data archive of the deb.
+    path: &'a Path,
+}
+
+impl<'a> Component<'a> {
+    fn parse(
+        node: Node,
+        package: &'a str,
+        deb: &'a Path,
+        files: &'a AppStreamFiles,
+    ) -> Option<Self> {
+        let mut component = Component {
+            kind: component_type(node.attribute("type").unwrap_or("generic")),
+            package,
+            ..Default::default()
+        };
+
+        let mut icon = None;
+        for child in node.children().filter(Node::is_element) {
+            let language = language(child, "C");
+            let text = || child.text().unwrap_or("").trim().to_owned();
+            match child.tag_name().name() {
+                "id" => component.id = text(),
+                "name" => {
+                    component.name.insert(language, text());
+                }
+                "summary" => {
+                    component.summary.insert(language, text());
+                }
+                "developer_name" => {
+                    component.developer_name.insert(language, text());
+                }
+                "developer" => {
+                    for name in child.children().filter(|node| node.has_tag_name("name")) {
+                        let text = name.text().unwrap_or("").trim().to_owned();
+                        component
+                            .developer_name
+                            .insert(self::language(name, "C"), text);
+                    }
+                }
+                "description" => description(child, &language, &mut component.description),
+                "project_license" => component.project_license = Some(text()),
+                "categories" => component.categories.extend(child_texts(child, "category")),
+                "keywords" => component.keywords.extend(child_texts(child, "keyword")),
+                "url" => {
+                    if let Some(kind) = child.attribute("type") {
+                        component.urls.insert(kind.to_owned(), text());
+                    }
+                }
+                "launchable" if child.attribute("type") == Some("desktop-id") => {
+                    component.launchables.push(text());
+                }
+                "icon" if matches!(child.attribute("type"), Some("stock") | Some("cached")) => {
+                    icon = Some(text());
+                }
+                _ => (),
+            }
+        }
+
+        if component.id.is_empty() || !component.name.contains_key("C") {
+            return None;
+        }
+
+        if component.launchables.is_empty()
+            && component.id.ends_with(".desktop")
+            && files.desktop.contains_key(&component.id)
+        {
+            component.launchables.push(component.id.clone());
+        }
+
+        // The desktop entry of the application provides what the metainfo file did not.
+        if let Some(entry) = component
+            .launchables
+            .iter()
+            .find_map(|id| files.desktop.get(id))
+            .map(|entry| desktop_entry(entry))
+        {
+            let list = |key| {
+                entry
+                    .get(key)
+                    .map(|value: &&str| {
+                        value.split(';').filter(|v| !v.is_empty()).map(String::from)
+                    })
+                    .into_iter()
+                    .flatten()
+            };
+
+            if component.categories.is_empty() {
+                component.categories.extend(list("Categories"));
+            }
+
+            if component.keywords.is_empty() {
+                component.keywords.extend(list("Keywords"));
+            }
+
+            if icon.is_none() {
+                icon = entry.get("Icon").map(|icon| icon.to_string());
+            }
+        }
+
+        if let Some(icon) = icon.filter(|icon| !icon.is_empty()) {
+            // Icons may be given as a path, or as a file name with an extension.
+            let name = Path::new(&icon)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or(&icon)
+                .to_owned();
+
+            if let Some(path) = files.icons.get(&name) {
+                component.cached_icon = Some(CachedIcon {
+                    name: [package, "_", &name, ".png"].concat(),
+                    deb,
+                    path,
+                });
+            }
+
+            if !icon.contains('/') {
+                component.stock_icon = Some(name);
+            }
+        }
+
+        Some(component)
+    }
+
+    /// Writes the component as a document of the DEP-11 YAML stream.
+    fn write_yaml(&self, out: &mut String) {
+        out.push_str("---\n");
+        let _ = writeln!(out, "Type: {}", self.kind);
+        let _ = writeln!(out, "ID: {}", quote(&self.id));
+        let _ = writeln!(out, "Package: {}", quote(self.package));
+        write_localized(out, "Name", &self.name);
+        write_localized(out, "Summary", &self.summary);
+        write_localized(out, "Description", &self.description);
+        write_localized(out, "DeveloperName", &self.developer_name);
+
+        if let Some(ref license) = self.project_license {
+            let _ = writeln!(out, "ProjectLicense: {}", quote(license));
+        }
+
+        write_list(out, "Categories", "", &self.categories);
+        if !self.keywords.is_empty() {
+            out.push_str("Keywords:\n");
+            write_list(out, "C", "  ", &self.keywords);
+        }
+
+        if !self.urls.is_empty() {
+            out.push_str("Url:\n");
+            for (kind, url) in &self.urls {
+                let _ = writeln!(out, "  {}: {}", key(kind), quote(url));
+            }
+        }
+
+        if !self.launchables.is_empty() {
+            out.push_str("Launchable:\n");
+            write_list(out, "desktop-id", "  ", &self.launchables);
+        }
+
+        if self.stock_icon.is_some() || self.cached_icon.is_some() {
+            out.push_str("Icon:\n");
+            if let Some(ref stock) = self.stock_icon {
+                let _ = writeln!(out, "  stock: {}", quote(stock));
+            }
+
+            if let Some(ref icon) = self.cached_icon {
+                let _ = writeln!(out, "  cached:\n    - name: {}", quote(&icon.name));
+                let _ = writeln!(
+                    out,
+                    "      width: {}\n      height: {}",
+                    ICON_SIZE, ICON_SIZE
+                );
+            }
+        }
+    }
+}
+
+/// Writes the `dep11/Components-<arch>.yml` metadata of each component, and the tarball of
+/// the icons that it references, for the components which contain AppStream metadata.
+pub fn write_appstream(
+    path: &Path,
+    config: &Config,
+    entries: &Entries,
+    options: &CompressOptions,
+) -> io::Result<()> {
+    let architectures = config.arches();
+    let components = entries
+        .values()
+        .flat_map(|(packages, _)| packages.keys())
+        .collect::<BTreeSet<_>>();
+
+    // Identifies the metadata of this suite and component, which clients cache icons under.
+    let origin = config
+        .origin
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+
+    components
+        .into_par_iter()
+        .map(|component| {
+            let packages_of = |arch: &str| -> &[PackageEntry] {
+                entries
+                    .get(arch)
+                    .and_then(|(packages, _)| packages.get(component))
+                    .map_or(&[], Vec::as_slice)
+            };
+
+            // Packages of the `all` architecture are described in the metadata of every
+            // architecture, unless they have already been merged into each of them.
+            let all = match config.arch_all {
+                ArchAll::Separate => components_of(packages_of(ALL)),
+                ArchAll::Merged => Vec::new(),
+            };
+
+            let arches = architectures
+                .iter()
+                .map(|arch| (arch, components_of(packages_of(arch))))
+                .collect::<Vec<_>>();
+
+            if all.is_empty() && arches.iter().all(|(_, components)| components.is_empty()) {
+                return Ok(());
+            }
+
+            let dep11 = path.join(component).join("dep11");
+            fs::create_dir_all(&dep11)?;
+
+            let mut icons = BTreeMap::new();
+            for (arch, components) in &arches {
+                let mut yaml = String::with_capacity(64 * 1024);
+                let _ = writeln!(yaml, "---\nFile: DEP-11\nVersion: '{}'", DEP11_VERSION);
+                let _ = writeln!(
+                    yaml,
+                    "Origin: {}",
+                    quote(&[&origin, "-", &config.archive, "-", component].concat())
+                );
+
+                let mut components = components.iter().chain(all.iter()).collect::<Vec<_>>();
+                components.sort_by(|a, b| (&a.id, a.package).cmp(&(&b.id, b.package)));
+                components.dedup_by(|a, b| (&a.id, a.package) == (&b.id, b.package));
+                for component in components {
+                    component.write_yaml(&mut yaml);
+                    if let Some(ref icon) = component.cached_icon {
+                        icons.insert(icon.name.as_str(), icon);
+                    }
+                }
+
+                compress(
+                    &["Components-", arch, ".yml"].concat(),
+                    &dep11,
+                    yaml.as_bytes(),
+                    options,
+                )?;
+            }
+
+            if !icons.is_empty() {
+                let tarball = icons_tarball(&icons)?;
+                let options = CompressOptions {
+                    support: UNCOMPRESSED,
+                    ..*options
+                };
+
+                compress(ICONS_TARBALL, &dep11, Cursor::new(tarball), &options)?;
+            }
+
+            Ok(())
+        })
+        .collect::<io::Result<()>>()
+}
+
+/// Parses the AppStream components provided by each of the packages.
+fn components_of(packages: &[PackageEntry]) -> Vec<Component<'_>> {
+    packages
+        .iter()
+        .filter(|package| !package.appstream.metainfo.is_empty())
+        .flat_map(|package| {
+            let name = control::get(&package.control, "Package").unwrap_or_default();
+            package.appstream.components(name, &package.filename)
+        })
+        .collect()
+}
+
+/// Creates a gzipped tarball of the icons, which clients extract into their icon cache. The
+/// icons are read from the data archive of each deb which contains them.
+fn icons_tarball(icons: &BTreeMap<&str, &CachedIcon>) -> io::Result<Vec<u8>> {
+    let mut debs: BTreeMap<&Path, HashMap<&Path, Vec<&str>>> = BTreeMap::new();
+    for (name, icon) in icons {
+        debs.entry(icon.deb)
+            .or_default()
+            .entry(icon.path)
+            .or_default()
+            .push(name);
+    }
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::Best));
+    for (deb, wanted) in debs {
+        let mut found = 0;
+        DebArchive::new(deb)?.data(|entry| {
+            let path = entry.path()?;
+            let path = path.strip_prefix("./").unwrap_or(&path);
+            let names = match wanted.get(path) {
+                Some(names) => names,
+                None => return Ok(()),
+            };
+
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            for name in names {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, name, data.as_slice())?;
+            }
+
+            found += 1;
+            Ok(())
+        })?;
+
+        if found != wanted.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("icons are missing from {}", deb.display()),
+            ));
+        }
+    }
+
+    builder.into_inner()?.finish()
+}
+
+fn read_string<R: Read>(file: &mut R) -> io::Result<String> {
+    let mut string = String::new();
+    file.read_to_string(&mut string)?;
+    Ok(string)
+}
+
+/// Reads the width and height of a PNG image from its header.
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || &data[..8] != b"\x89PNG\r\n\x1a\n" || &data[12..16] != b"IHDR" {
+        return None;
+    }
+
+    let read = |at: usize| u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+    Some((read(16), read(20)))
+}
+
+/// Maps the type of a metainfo component to its DEP-11 type.
+fn component_type(kind: &str) -> &'static str {
+    match kind {
+        "desktop" | "desktop-application" => "desktop-application",
+        "console-application" => "console-application",
+        "web-application" => "web-application",
+        "addon" => "addon",
+        "codec" => "codec",
+        "driver" => "driver",
+        "firmware" => "firmware",
+        "font" => "font",
+        "icon-theme" => "icon-theme",
+        "inputmethod" => "inputmethod",
+        "localization" => "localization",
+        "operating-system" => "operating-system",
+        "runtime" => "runtime",
+        "service" => "service",
+        _ => "generic",
+    }
+}
+
+fn language(node: Node, default: &str) -> String {
+    node.attribute((NS_XML_URI, "lang"))
+        .unwrap_or(default)
+        .to_owned()
+}
+
+fn child_texts<'a>(node: Node<'a, 'a>, tag: &'a str) -> impl Iterator<Item = String> + 'a {
+    node.children()
+        .filter(move |child| {
+            child.has_tag_name(tag) && child.attribute((NS_XML_URI, "lang")).is_none()
+        })
+        .filter_map(|child| child.text())
+        .map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty())
+}
+
+/// Collects the markup of a description for each language. Paragraphs may be translated
+/// individually, or the entire description may be translated.
+fn description(node: Node, language: &str, descriptions: &mut BTreeMap<String, String>) {
+    for child in node.children().filter(Node::is_element) {
+        let tag = child.tag_name().name();
+        let markup = match tag {
+            "ul" | "ol" => {
+                let items = child
+                    .children()
+                    .filter(|item| item.has_tag_name("li"))
+                    .map(|item| ["<li>", &inline_markup(item), "</li>"].concat())
+                    .collect::<String>();
+                format!("<{0}>{1}</{0}>", tag, items)
+            }
+            "p" => ["<p>", &inline_markup(child), "</p>"].concat(),
+            _ => continue,
+        };
+
+        descriptions
+            .entry(self::language(child, language))
+            .or_default()
+            .push_str(&markup);
+    }
+}
+
+/// Serializes the text of an element, and the emphasis and code elements within it.
+fn inline_markup(node: Node) -> String {
+    let mut markup = String::new();
+    for child in node.children() {
+        if child.is_text() {
+            markup.push_str(&escape_xml(child.text().unwrap_or("")));
+        } else if child.is_element() {
+            let tag = child.tag_name().name();
+            if tag == "em" || tag == "code" {
+                let _ = write!(markup, "<{0}>{1}</{0}>", tag, inline_markup(child));
+            } else {
+                markup.push_str(&inline_markup(child));
+            }
+        }
+    }
+
+    markup.split_whitespace().join(" ")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Parses the keys of the `[Desktop Entry]` group of a desktop entry.
+fn desktop_entry(entry: &str) -> HashMap<&str, &str> {
+    let mut keys = HashMap::new();
+    let mut in_group = false;
+    for line in entry.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+        } else if in_group && let Some((key, value)) = line.split_once('=') {
+            keys.insert(key.trim(), value.trim());
+        }
+    }
+
+    keys
+}
+
+fn write_localized(out: &mut String, field: &str, values: &BTreeMap<String, String>) {
+    if values.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(out, "{}:", field);
+    for (language, value) in values {
+        let _ = writeln!(out, "  {}: {}", key(language), quote(value));
+    }
+}
+
+fn write_list(out: &mut String, field: &str, indent: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(out, "{}{}:", indent, field);
+    for value in values {
+        let _ = writeln!(out, "{}  - {}", indent, quote(value));
+    }
+}
+
+/// Mapping keys such as languages are written plainly, unless they need to be quoted.
+fn key(key: &str) -> String {
+    let plain = key.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-@.".contains(c));
+
+    if plain { key.to_owned() } else { quote(key) }
+}
+
+/// Writes a value as a double-quoted YAML scalar.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dep11_component() {
+        let mut files = AppStreamFiles::default();
+        files.metainfo.push(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<component type="desktop-application">
+  <id>com.system76.Popsicle</id>
+  <name>Popsicle</name>
+  <name xml:lang="de">Eis am Stiel</name>
+  <summary>Flash multiple "USB" devices</summary>
+  <description>
+    <p>Flash images to
+       <em>many</em> devices &amp; more.</p>
+    <ul><li>Fast</li><li>Safe</li></ul>
+  </description>
+  <launchable type="desktop-id">com.system76.Popsicle.desktop</launchable>
+  <url type="homepage">https://github.com/pop-os/popsicle</url>
+</component>"#
+                .into(),
+        );
+
+        files.desktop.insert(
+            "com.system76.Popsicle.desktop".into(),
+            "[Desktop Entry]\nName=Popsicle\nIcon=popsicle\nCategories=System;Utility;\n".into(),
+        );
+
+        files.icons.insert(
+            "popsicle".into(),
+            "usr/share/icons/hicolor/64x64/apps/popsicle.png".into(),
+        );
+
+        let deb = Path::new("pool/main/p/popsicle/popsicle-gtk_1.0_amd64.deb");
+        let components = files.components("popsicle-gtk", deb);
+        assert_eq!(components.len(), 1);
+
+        let mut yaml = String::new();
+        components[0].write_yaml(&mut yaml);
+        assert_eq!(
+            yaml,
+            r#"---
+Type: desktop-application
+ID: "com.system76.Popsicle"
+Package: "popsicle-gtk"
+Name:
+  C: "Popsicle"
+  de: "Eis am Stiel"
+Summary:
+  C: "Flash multiple \"USB\" devices"
+Description:
+  C: "<p>Flash images to <em>many</em> devices &amp; more.</p><ul><li>Fast</li><li>Safe</li></ul>"
+Categories:
+  - "System"
+  - "Utility"
+Url:
+  homepage: "https://github.com/pop-os/popsicle"
+Launchable:
+  desktop-id:
+    - "com.system76.Popsicle.desktop"
+Icon:
+  stock: "popsicle"
+  cached:
+    - name: "popsicle-gtk_popsicle.png"
+      width: 64
+      height: 64
+"#
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::ContentsEntry;
+use crate::debian::control::{self, Fields};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// What to do when packages ship the same file, without declaring a relationship which
+/// permits dpkg to install them together.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateFiles {
+    Ignore,
+    /// Log a warning for each pair of packages.
+    #[default]
+    Warn,
+    /// Also fail the generation of the dist files.
+    Error,
+}
+
+/// The relationships of a package which determine if it may ship the files of another package.
+#[derive(Clone, Debug, Default)]
+pub struct Relations {
+    /// The name of the package.
+    pub name: String,
+    /// The virtual packages which it provides.
+    pub provides: Vec<String>,
+    /// Packages which it has declared `Replaces` or `Conflicts` with. `Breaks` alone does not
+    /// permit dpkg to overwrite the files of another package.
+    pub replaces: Vec<String>,
+}
+
+impl Relations {
+    pub fn new(control: &Fields) -> Self {
+        let names = |keys: &[&str]| {
+            keys.iter()
+                .filter_map(|key| control::get(control, key))
+                .flat_map(relation_names)
+                .collect::<Vec<_>>()
+        };
+
+        Relations {
+            name: control::get(control, "Package")
+                .unwrap_or_default()
+                .to_owned(),
+            provides: names(&["Provides"]),
+            replaces: names(&["Replaces", "Conflicts"]),
+        }
+    }
+
+    /// Whether either package has declared a relationship with the other.
+    fn related(&self, other: &Relations) -> bool {
+        let declares = |a: &Relations, b: &Relations| {
+            a.replaces
+                .iter()
+                .any(|name| *name == b.name || b.provides.contains(name))
+        };
+
+        declares(self, other) || declares(other, self)
+    }
+}
+
+/// Gets the package names from a relationship field, without their versions and architectures.
+fn relation_names(field: &str) -> impl Iterator<Item = String> + '_ {
+    field
+        .split([',', '|'])
+        .filter_map(|relation| {
+            relation
+                .trim()
+                .split([' ', '(', ':', '['])
+                .next()
+                .filter(|name| !name.is_empty())
+        })
+        .map(String::from)
+}
+
+/// Finds files which are shipped by more than one package, where neither package has declared
+/// a relationship with the other. Each pair of packages is returned with one of their files,
+/// and how many files they share.
+pub fn find_duplicates<'a>(
+    contents: impl Iterator<Item = &'a ContentsEntry>,
+) -> Vec<(&'a str, &'a str, &'a Path, usize)> {
+    let mut owners: HashMap<&Path, Vec<&ContentsEntry>> = HashMap::new();
+    for entry in contents {
+        for file in &entry.files {
+            // Directories are shared between packages.
+            if !file.as_os_str().as_bytes().ends_with(b"/") {
+                owners.entry(file.as_path()).or_default().push(entry);
+            }
+        }
+    }
+
+    let mut duplicates: BTreeMap<(&str, &str), (&Path, usize)> = BTreeMap::new();
+    for (file, entries) in owners.into_iter().filter(|(_, entries)| entries.len() > 1) {
+        let mut pairs = BTreeSet::new();
+        for (position, a) in entries.iter().enumerate() {
+            for b in &entries[position + 1..] {
+                // Versions of the same package are never installed together.
+                if a.relations.name == b.relations.name || a.relations.related(&b.relations) {
+                    continue;
+                }
+
+                pairs.insert(if a.relations.name < b.relations.name {
+                    (a.relations.name.as_str(), b.relations.name.as_str())
+                } else {
+                    (b.relations.name.as_str(), a.relations.name.as_str())
+                });
+            }
+        }
+
+        for pair in pairs {
+            let duplicate = duplicates.entry(pair).or_insert((file, 0));
+            duplicate.0 = duplicate.0.min(file);
+            duplicate.1 += 1;
+        }
+    }
+
+    duplicates
+        .into_iter()
+        .map(|((a, b), (file, count))| (a, b, file, count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(control: &str, files: &[&str]) -> ContentsEntry {
+        let relations = Relations::new(&control::parse(control));
+        ContentsEntry {
+            package: ["utils/", &relations.name].concat(),
+            files: files.iter().map(PathBuf::from).collect(),
+            relations,
+        }
+    }
+
+    #[test]
+    fn duplicate_files() {
+        let contents = [
+            entry("Package: a\n", &["./usr/", "./usr/bin/a", "./usr/bin/tool"]),
+            entry("Package: b\n", &["./usr/", "./usr/bin/tool"]),
+            entry(
+                "Package: c\nBreaks: a (<< 2.0)\nReplaces: a:any (<< 2.0)\n",
+                &["./usr/bin/a"],
+            ),
+            entry("Package: d\nConflicts: mta\n", &["./usr/sbin/sendmail"]),
+            entry("Package: e\nProvides: mta\n", &["./usr/sbin/sendmail"]),
+            entry("Package: b\n", &["./usr/bin/tool"]),
+            entry("Package: f\n", &["./usr/share/f"]),
+            entry("Package: g\nBreaks: f (<< 2.0)\n", &["./usr/share/f"]),
+        ];
+
+        assert_eq!(
+            find_duplicates(contents.iter()),
+            vec![
+                ("a", "b", Path::new("./usr/bin/tool"), 1),
+                ("f", "g", Path::new("./usr/share/f"), 1)
+            ]
+        );
+    }
+}
@@ -1,13 +1,23 @@
+mod appstream;
+mod duplicates;
 mod package;
+mod source;
+mod translation;
 
+pub use self::appstream::*;
+pub use self::duplicates::*;
 pub use self::package::*;
+pub use self::source::*;
+pub use self::translation::*;
 use super::*;
 use crate::config::Config;
+use crate::debian::arch::{self, ArchAll};
 use crate::iter_reader::IteratorReader;
 use itertools::Itertools;
 use rayon;
 use rayon::prelude::*;
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io::{self, Write};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
@@ -22,32 +32,106 @@ impl<'a> DistFiles<'a> {
         DistFiles { path, entries }
     }
 
-    // pub fn check_for_duplicates(&self) {
-    //     let contents = &self.contents;
-    //     for (arch, (packages, contents)) in &self.entries {
-    //
-    //     }
-    //     contents.windows(2)
-    //         .position(|window| window[0] == window[1])
-    //         .map_or(Ok(()), |pos| {
-    //             let a = &contents[pos];
-    //             let b = &contents[pos+1];
-    //             Err(io::Error::new(
-    //                 io::ErrorKind::Other,
-    //                 format!("{} and {} both have {}", a.1, b.1, a.0.display())
-    //             ))
-    //         })
-    // }
+    /// Reports packages of each architecture which ship the same file, without declaring a
+    /// relationship that allows dpkg to install them together.
+    pub fn check_for_duplicates(&self, config: &Config) -> io::Result<()> {
+        if config.duplicate_files == DuplicateFiles::Ignore {
+            return Ok(());
+        }
+
+        let contents_of = |arch: &str| {
+            self.entries
+                .get(arch)
+                .map_or(&[][..], |(_, contents)| contents.as_slice())
+        };
+
+        // Packages of the `all` architecture may be installed alongside those of any other.
+        let all = match config.arch_all {
+            ArchAll::Separate => contents_of(arch::ALL),
+            ArchAll::Merged => &[],
+        };
+
+        let architectures = config.arches();
+        let mut duplicates: BTreeMap<_, (&Path, usize, Vec<&str>)> = BTreeMap::new();
+        for arch in architectures.iter() {
+            for (a, b, file, count) in find_duplicates(contents_of(arch).iter().chain(all)) {
+                let duplicate = duplicates
+                    .entry((a, b))
+                    .or_insert((file, count, Vec::new()));
+                duplicate.2.push(arch);
+            }
+        }
+
+        for ((a, b), (file, count, arches)) in &duplicates {
+            let file = file.strip_prefix("./").unwrap_or(file).display();
+            let files = match count {
+                1 => file.to_string(),
+                _ => format!("{} and {} other files", file, count - 1),
+            };
+
+            log::warn!(
+                "{} and {} both ship {} on {}, without Replaces, Breaks, or Conflicts between them",
+                a,
+                b,
+                files,
+                arches.join(", ")
+            );
+        }
+
+        if config.duplicate_files == DuplicateFiles::Error && !duplicates.is_empty() {
+            return Err(io::Error::other(format!(
+                "{} pairs of packages ship the same files",
+                duplicates.len()
+            )));
+        }
+
+        Ok(())
+    }
 
     pub fn compress_and_release(
         self,
         config: &Config,
+        options: &CompressOptions,
         origin: &str,
         bugs: Option<&str>,
+        translations: Option<&Path>,
     ) -> io::Result<()> {
-        let entries = self.entries;
+        let mut entries = self.entries;
         let path = self.path;
 
+        // AppStream metadata is generated for each component which has packages that provide it.
+        write_appstream(path, config, &entries, options)?;
+
+        // If translations are enabled, long descriptions are moved out of the Packages indices,
+        // and into the Translation indices of each component. `translations` is where the
+        // translated descriptions of each package may be found.
+        if let Some(translated) = translations {
+            let mut descriptions: BTreeMap<&str, Descriptions> = BTreeMap::new();
+            for (packages, _) in entries.values_mut() {
+                for (component, packages) in packages.iter_mut() {
+                    for package in packages {
+                        if let Some((md5sum, description)) = package.split_description()? {
+                            let name = control::get(&package.control, "Package")
+                                .unwrap_or_default()
+                                .to_owned();
+                            descriptions
+                                .entry(component)
+                                .or_default()
+                                .insert((name, md5sum), description);
+                        }
+                    }
+                }
+            }
+
+            descriptions
+                .par_iter()
+                .map(|(component, descriptions)| {
+                    let i18n = path.join(component).join("i18n");
+                    write_translations(&i18n, descriptions, translated, options)
+                })
+                .collect::<io::Result<()>>()?;
+        }
+
         // Processes each architecture in parallel, including the contents archives for each arch.
         entries
             .into_par_iter()
@@ -56,44 +140,11 @@ impl<'a> DistFiles<'a> {
                 let (contents_res, packages_res) = rayon::join(
                     // Generate and compress the Contents archive for each architecture in parallel.
                     // Contents are processed in a per-architecture manner, rather than per-component.
-                    || {
-                        // Sort the files beforehand, so files are easy to track down.
-                        // This will require that we generate the contents archive in advance, sadly.
-                        let mut contents =
-                            ContentsIterator::new(contents).collect::<Vec<Vec<u8>>>();
-                        contents.par_sort_unstable_by(|a, b| a.cmp(&b));
-
-                        let contents_reader = IteratorReader::new(
-                            contents.into_iter(),
-                            Vec::with_capacity(64 * 1024),
-                        );
-
-                        // Similar to the Packages archives, we also need an uncompressed variant of
-                        // the compressed archives to satisfy APT's detection capabilities.
-                        compress(
-                            &["Contents-", &arch].concat(),
-                            path,
-                            contents_reader,
-                            UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS | ZSTD_COMPRESS,
-                        )
-                    },
+                    || write_contents(&["Contents-", arch].concat(), path, contents, options),
                     // Generate & compress each Packages archive for each architecture & component in parallel.
                     // Packages archives are processed in a per-architecture, per-component manner.
                     || {
-                        let arch_dir = match arch {
-                            "amd64" => "binary-amd64",
-                            "arm64" => "binary-arm64",
-                            "armel" => "binary-armel",
-                            "armhf" => "binary-armhf",
-                            "i386" => "binary-i386",
-                            "mips" => "binary-mips",
-                            "mipsel" => "binary-mipsel",
-                            "mips64el" => "binary-mips64el",
-                            "ppc64el" => "binary-ppc64el",
-                            "s390x" => "binary-s390x",
-                            "all" => "binary-all",
-                            arch => panic!("unsupported architecture: {}", arch),
-                        };
+                        let arch_dir = &arch::binary_dir(arch);
 
                         // Processes the packages of each component in parallel, for this architecture.
                         packages
@@ -101,6 +152,7 @@ impl<'a> DistFiles<'a> {
                             .map(|(component, mut packages)| {
                                 // Construct the path where the Packages archives will be written.
                                 let binary_path = &path.join(&component).join(arch_dir);
+                                fs::create_dir_all(binary_path)?;
 
                                 // Sort the packages that were collected before we generate them for writing.
                                 packages.par_sort_unstable_by(|a, b| a.filename.cmp(&b.filename));
@@ -123,22 +175,17 @@ impl<'a> DistFiles<'a> {
 
                                 // Although we will generate a compressed GZ and XZ archive for our
                                 // repository, APT still requires that we also write an uncompressed variant.
-                                compress(
-                                    "Packages",
-                                    binary_path,
-                                    packages_reader,
-                                    UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS | ZSTD_COMPRESS,
-                                )
-                                .map_err(|why| {
-                                    io::Error::new(
-                                        io::ErrorKind::Other,
-                                        format!(
-                                            "failed to generate content archive at {}: {}",
-                                            path.display(),
-                                            why
-                                        ),
-                                    )
-                                })?;
+                                compress("Packages", binary_path, packages_reader, options)
+                                    .map_err(|why| {
+                                        io::Error::new(
+                                            io::ErrorKind::Other,
+                                            format!(
+                                                "failed to generate content archive at {}: {}",
+                                                path.display(),
+                                                why
+                                            ),
+                                        )
+                                    })?;
 
                                 // A release file also needs to be stored in the same location, after the
                                 // archives have been written. This contains the checksums for each file.
@@ -176,6 +223,25 @@ impl<'a> DistFiles<'a> {
     }
 }
 
+/// Writes the files of each package to a Contents index.
+pub fn write_contents(
+    name: &str,
+    path: &Path,
+    contents: Vec<ContentsEntry>,
+    options: &CompressOptions,
+) -> io::Result<()> {
+    // Sort the files beforehand, so files are easy to track down.
+    // This will require that we generate the contents archive in advance, sadly.
+    let mut contents = ContentsIterator::new(contents).collect::<Vec<Vec<u8>>>();
+    contents.par_sort_unstable();
+
+    let contents_reader = IteratorReader::new(contents.into_iter(), Vec::with_capacity(64 * 1024));
+
+    // Similar to the Packages archives, we also need an uncompressed variant of
+    // the compressed archives to satisfy APT's detection capabilities.
+    compress(name, path, contents_reader, options)
+}
+
 /// Efficiently generate each line of the Contents file, in style.
 pub struct ContentsIterator {
     contents: Vec<ContentsEntry>,
@@ -228,9 +294,11 @@ impl Iterator for ContentsIterator {
     }
 }
 
+#[derive(Clone)]
 pub struct ContentsEntry {
     pub package: String,
     pub files: Vec<PathBuf>,
+    pub relations: Relations,
 }
 
 fn inner_write_release_file(
@@ -245,5 +313,34 @@ fn inner_write_release_file(
     writeln!(&mut release, "Component: {}", component)?;
     writeln!(&mut release, "Origin: {}", config.origin)?;
     writeln!(&mut release, "Label: {}", config.label)?;
+    write_release_fields(&mut release, config)?;
     writeln!(&mut release, "Architecture: {}", arch)
 }
+
+/// Writes the optional fields from the suite's config which are shared by every Release file.
+///
+/// `Valid-Until` is excluded, as it is relative to the `Date` of the top-level Release file.
+pub fn write_release_fields<W: Write>(release: &mut W, config: &Config) -> io::Result<()> {
+    let fields = &config.release;
+    if fields.not_automatic {
+        writeln!(release, "NotAutomatic: yes")?;
+    }
+
+    if fields.but_automatic_upgrades {
+        writeln!(release, "ButAutomaticUpgrades: yes")?;
+    }
+
+    if config.by_hash.enabled {
+        writeln!(release, "Acquire-By-Hash: yes")?;
+    }
+
+    if let Some(ref changelogs) = fields.changelogs {
+        writeln!(release, "Changelogs: {}", changelogs)?;
+    }
+
+    if !fields.signed_by.is_empty() {
+        writeln!(release, "Signed-By: {}", fields.signed_by.join(", "))?;
+    }
+
+    Ok(())
+}
@@ -1,97 +1,167 @@
-use std::collections::BTreeMap;
+use super::AppStreamFiles;
+use crate::debian::control::{self, Fields};
 use std::io;
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 
+/// Fields which must be defined by the control file of every package in the pool.
+const REQUIRED: &[&str] = &[
+    "Package",
+    "Architecture",
+    "Version",
+    "Priority",
+    "Section",
+    "Maintainer",
+    "Installed-Size",
+];
+
+/// The order in which fields are written to a Packages stanza, as APT does. Fields which are not
+/// in this list are written after these fields, in the order of the package's control file.
+const CANONICAL_ORDER: &[&str] = &[
+    "Package",
+    "Package-Type",
+    "Architecture",
+    "Subarchitecture",
+    "Version",
+    "Revision",
+    "Config-Version",
+    "Multi-Arch",
+    "Status",
+    "Priority",
+    "Class",
+    "Essential",
+    "Protected",
+    "Installer-Menu-Item",
+    "Section",
+    "Source",
+    "Origin",
+    "Maintainer",
+    "Original-Maintainer",
+    "Bugs",
+    "Conffiles",
+    "Triggers-Awaited",
+    "Triggers-Pending",
+    "Installed-Size",
+    "Provides",
+    "Pre-Depends",
+    "Depends",
+    "Recommends",
+    "Suggests",
+    "Conflicts",
+    "Breaks",
+    "Replaces",
+    "Enhances",
+    "Filename",
+    "MSDOS-Filename",
+    "Size",
+    "MD5sum",
+    "SHA1",
+    "SHA256",
+    "SHA512",
+    "Description",
+    "Description-md5",
+    "Homepage",
+    "Tag",
+    "Task",
+    "Built-Using",
+    "Static-Built-Using",
+    "Built-For-Profiles",
+    "Auto-Built-Package",
+    "Build-Ids",
+];
+
+#[derive(Clone)]
 pub struct PackageEntry {
-    pub control: BTreeMap<String, String>,
+    pub control: Fields,
     pub filename: PathBuf,
     pub size: u64,
     pub md5sum: String,
     pub sha1: String,
     pub sha256: String,
     pub sha512: String,
+    pub appstream: AppStreamFiles,
 }
 
 impl PackageEntry {
-    pub fn generate_entry(mut self, origin: &str, bugs: Option<&str>) -> io::Result<Vec<u8>> {
-        let mut output = Vec::with_capacity(1024);
-        let control = &mut self.control;
-
-        fn get_key(map: &mut BTreeMap<String, String>, key: &str) -> io::Result<String> {
-            map.remove(key).ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::NotFound,
-                    format!("{} not found in control file", key),
-                )
-            })
+    pub fn generate_entry(self, origin: &str, bugs: Option<&str>) -> io::Result<Vec<u8>> {
+        if let Some(key) = REQUIRED
+            .iter()
+            .find(|key| control::get(&self.control, key).is_none())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found in control file", key),
+            ));
         }
 
-        fn write_entry(output: &mut Vec<u8>, key: &[u8], value: &[u8]) {
-            output.extend_from_slice(key);
-            output.extend_from_slice(b": ");
-            output.extend_from_slice(value);
-            output.push(b'\n');
-        }
+        let size = self.size.to_string();
+        let mut output = Vec::with_capacity(1024);
 
-        macro_rules! write_from_map {
-            ($key:expr) => {
-                write_entry(
-                    &mut output,
-                    $key.as_bytes(),
-                    get_key(control, $key)?.as_bytes(),
-                );
+        for &key in CANONICAL_ORDER {
+            // Fields describing the package in the repository take precedence over the control file.
+            let value = match key {
+                "Origin" => Some(origin.as_bytes()),
+                "Bugs" => bugs.map(str::as_bytes),
+                "Filename" => Some(self.filename.as_os_str().as_bytes()),
+                "Size" => Some(size.as_bytes()),
+                "MD5sum" => Some(self.md5sum.as_bytes()),
+                "SHA1" => Some(self.sha1.as_bytes()),
+                "SHA256" => Some(self.sha256.as_bytes()),
+                "SHA512" => Some(self.sha512.as_bytes()),
+                _ => None,
             };
-        }
 
-        macro_rules! optional_map {
-            ($key:expr) => {
-                if let Some(value) = control.remove($key) {
-                    if let Some(value) = value.lines().next() {
-                        write_entry(&mut output, $key.as_bytes(), value.as_bytes());
-                    }
-                }
-            };
+            if let Some(value) =
+                value.or_else(|| control::get(&self.control, key).map(str::as_bytes))
+            {
+                control::write_field(&mut output, key.as_bytes(), value);
+            }
         }
 
-        write_from_map!("Package");
-        optional_map!("Package-Type");
-        write_from_map!("Architecture");
-        write_from_map!("Version");
-        optional_map!("Multi-Arch");
-        optional_map!("Auto-Built-Package");
-        write_from_map!("Priority");
-        write_from_map!("Section");
-        write_entry(&mut output, b"Origin", origin.as_bytes());
-        write_from_map!("Maintainer");
-        write_from_map!("Installed-Size");
-        optional_map!("Provides");
-        optional_map!("Pre-Depends");
-        optional_map!("Depends");
-        optional_map!("Recommends");
-        optional_map!("Suggests");
-        optional_map!("Conflicts");
-        optional_map!("Breaks");
-        optional_map!("Replaces");
-        if let Some(bugs) = bugs {
-            write_entry(&mut output, b"Bugs", bugs.as_bytes());
+        for (key, value) in &self.control {
+            if !CANONICAL_ORDER
+                .iter()
+                .any(|field| field.eq_ignore_ascii_case(key))
+            {
+                control::write_field(&mut output, key.as_bytes(), value.as_bytes());
+            }
         }
-        write_entry(
-            &mut output,
-            b"Filename",
-            self.filename.as_os_str().as_bytes(),
-        );
-        write_entry(&mut output, b"Size", self.size.to_string().as_bytes());
-        write_entry(&mut output, b"MD5sum", self.md5sum.as_bytes());
-        write_entry(&mut output, b"SHA1", self.sha1.as_bytes());
-        write_entry(&mut output, b"SHA256", self.sha256.as_bytes());
-        write_entry(&mut output, b"SHA512", self.sha512.as_bytes());
-        optional_map!("Homepage");
-        optional_map!("Description");
-        optional_map!("License");
-        optional_map!("Vendor");
-        optional_map!("Build-Ids");
 
         Ok(output)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_stanza() {
+        let control = control::parse(
+            "Package: hello\nVersion: 1.0\nArchitecture: amd64\nMaintainer: Someone <a@b.c>\n\
+             Installed-Size: 10\nPriority: optional\nSection: utils\nX-Custom: value\n\
+             Description: summary\n long description\n .\n more\nBuilt-Using: foo (= 1.0)\n",
+        );
+
+        let entry = PackageEntry {
+            control,
+            filename: PathBuf::from("pool/main/h/hello/hello_1.0_amd64.deb"),
+            size: 1024,
+            md5sum: "md5".into(),
+            sha1: "sha1".into(),
+            sha256: "sha256".into(),
+            sha512: "sha512".into(),
+            appstream: AppStreamFiles::default(),
+        };
+
+        assert_eq!(
+            String::from_utf8(entry.generate_entry("Pop", None).unwrap()).unwrap(),
+            "Package: hello\nArchitecture: amd64\nVersion: 1.0\nPriority: optional\n\
+             Section: utils\nOrigin: Pop\nMaintainer: Someone <a@b.c>\nInstalled-Size: 10\n\
+             Filename: pool/main/h/hello/hello_1.0_amd64.deb\nSize: 1024\nMD5sum: md5\n\
+             SHA1: sha1\nSHA256: sha256\nSHA512: sha512\n\
+             Description: summary\n long description\n .\n more\n\
+             Built-Using: foo (= 1.0)\nX-Custom: value\n"
+        );
+    }
+}
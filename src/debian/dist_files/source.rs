@@ -0,0 +1,166 @@
+use crate::checksum::{Digests, hasher};
+use crate::debian::control::{self, Fields};
+use crate::misc;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// A file which belongs to a source package, including the `.dsc` itself.
+pub struct SourceFile {
+    pub name: String,
+    pub size: u64,
+    pub md5sum: String,
+    pub sha1: String,
+    pub sha256: String,
+    pub sha512: String,
+}
+
+type SourceDigest = fn(&SourceFile) -> &str;
+
+/// A source package from the pool, which will be written as a stanza in the `Sources` index.
+pub struct SourceEntry {
+    pub fields: Fields,
+    pub directory: PathBuf,
+    pub files: Vec<SourceFile>,
+}
+
+impl SourceEntry {
+    /// Reads the `.dsc` at the given path, and the files that it references.
+    pub fn new(dsc: &Path) -> io::Result<Self> {
+        let invalid = |why: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", dsc.display(), why),
+            )
+        };
+
+        let directory = dsc
+            .parent()
+            .ok_or_else(|| invalid("dsc has no parent directory"))?
+            .to_path_buf();
+
+        let name = dsc
+            .file_name()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| invalid("dsc has an invalid file name"))?
+            .to_owned();
+
+        let fields = control::parse(&control::strip_signature(&misc::read_to_string(dsc)?));
+        if control::get(&fields, "Source").is_none() {
+            return Err(invalid("Source not found in dsc"));
+        }
+
+        let mut files = vec![SourceFile::new(dsc, name)?];
+
+        // The checksums that the dsc has already recorded for each of its files.
+        let recorded = |key: &str, file: &str| -> Option<String> {
+            control::get(&fields, key)?.lines().find_map(|line| {
+                let mut columns = line.split_whitespace();
+                let digest = columns.next()?;
+                let _size = columns.next()?;
+                if columns.next()? == file {
+                    Some(digest.to_owned())
+                } else {
+                    None
+                }
+            })
+        };
+
+        let listed = control::get(&fields, "Files")
+            .ok_or_else(|| invalid("Files not found in dsc"))?
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace();
+                let md5sum = columns.next()?;
+                let size = columns.next()?.parse::<u64>().ok()?;
+                let name = columns.next()?;
+                Some((md5sum.to_owned(), size, name.to_owned()))
+            })
+            .collect::<Vec<_>>();
+
+        for (md5sum, size, name) in listed {
+            let path = directory.join(&name);
+            let digest =
+                |key: &str, hash: fn(File) -> io::Result<String>| match recorded(key, &name) {
+                    Some(digest) => Ok(digest),
+                    None => File::open(&path).and_then(hash),
+                };
+
+            let sha1 = digest("Checksums-Sha1", hasher::<Sha1, File>)?;
+            let sha256 = digest("Checksums-Sha256", hasher::<Sha256, File>)?;
+            let sha512 = digest("Checksums-Sha512", hasher::<Sha512, File>)?;
+
+            files.push(SourceFile {
+                name,
+                size,
+                md5sum,
+                sha1,
+                sha256,
+                sha512,
+            });
+        }
+
+        Ok(SourceEntry {
+            fields,
+            directory,
+            files,
+        })
+    }
+
+    /// Generates the `Sources` stanza for this source package.
+    pub fn generate_entry(self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(2048);
+
+        for (key, value) in &self.fields {
+            let key: &str = key;
+            match key {
+                "Source" => control::write_field(&mut output, b"Package", value.as_bytes()),
+                "Files" | "Checksums-Sha1" | "Checksums-Sha256" | "Checksums-Sha512" => (),
+                _ => control::write_field(&mut output, key.as_bytes(), value.as_bytes()),
+            }
+        }
+
+        control::write_field(
+            &mut output,
+            b"Directory",
+            self.directory.as_os_str().as_bytes(),
+        );
+
+        let sections: [(&[u8], SourceDigest); 4] = [
+            (b"Files", |file| &file.md5sum),
+            (b"Checksums-Sha1", |file| &file.sha1),
+            (b"Checksums-Sha256", |file| &file.sha256),
+            (b"Checksums-Sha512", |file| &file.sha512),
+        ];
+
+        for (key, digest) in &sections {
+            let mut value = String::new();
+            for file in &self.files {
+                value.push_str(&format!("\n {} {} {}", digest(file), file.size, file.name));
+            }
+
+            control::write_field(&mut output, key, value.as_bytes());
+        }
+
+        output
+    }
+}
+
+impl SourceFile {
+    fn new(path: &Path, name: String) -> io::Result<Self> {
+        let size = path.metadata()?.len();
+        let digests = Digests::from_path(path)?;
+
+        Ok(SourceFile {
+            name,
+            size,
+            md5sum: digests.md5sum,
+            sha1: digests.sha1,
+            sha256: digests.sha256,
+            sha512: digests.sha512,
+        })
+    }
+}
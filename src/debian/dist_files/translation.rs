@@ -0,0 +1,139 @@
+use super::PackageEntry;
+use crate::checksum::hasher;
+use crate::compress::{CompressOptions, compress};
+use crate::debian::control;
+use crate::iter_reader::IteratorReader;
+use crate::misc;
+use itertools::Itertools;
+use md5::Md5;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The full descriptions of the packages in a component, by package name and `Description-md5`.
+pub type Descriptions = BTreeMap<(String, String), String>;
+
+impl PackageEntry {
+    /// Replaces the long description of the package with the `Description-md5` of its full
+    /// description, which is returned so that it may be written to a Translation index.
+    pub fn split_description(&mut self) -> io::Result<Option<(String, String)>> {
+        let position = match self
+            .control
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case("Description"))
+        {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+
+        let description = self.control[position].1.clone();
+        let md5sum = hasher::<Md5, _>([&description, "\n"].concat().as_bytes())?;
+
+        let summary = description.lines().next().unwrap_or("").to_owned();
+        self.control[position].1 = summary;
+        self.control
+            .insert(position + 1, ("Description-md5".into(), md5sum.clone()));
+
+        Ok(Some((md5sum, description)))
+    }
+}
+
+/// Writes the `Translation-en` index of a component, and a `Translation-<lang>` index for each
+/// language that a description has been translated to.
+///
+/// Translated descriptions are read from `<translated>/<package>/i18n/Description-<lang>`,
+/// where the first line is the summary and the remaining lines are the long description.
+pub fn write_translations(
+    path: &Path,
+    descriptions: &Descriptions,
+    translated: &Path,
+    options: &CompressOptions,
+) -> io::Result<()> {
+    let mut indices: BTreeMap<String, Vec<Vec<u8>>> = BTreeMap::new();
+
+    for ((package, md5sum), description) in descriptions {
+        indices
+            .entry("en".into())
+            .or_default()
+            .push(stanza(package, md5sum, "en", description));
+
+        for (language, description) in translated_descriptions(translated, package)? {
+            indices.entry(language.clone()).or_default().push(stanza(
+                package,
+                md5sum,
+                &language,
+                &description,
+            ));
+        }
+    }
+
+    if indices.is_empty() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(path)?;
+    for (language, stanzas) in indices {
+        let reader = IteratorReader::new(
+            Itertools::intersperse(stanzas.into_iter(), vec![b'\n']),
+            Vec::with_capacity(64 * 1024),
+        );
+
+        compress(&["Translation-", &language].concat(), path, reader, options)?;
+    }
+
+    Ok(())
+}
+
+fn stanza(package: &str, md5sum: &str, language: &str, description: &str) -> Vec<u8> {
+    let mut output = Vec::with_capacity(512);
+    control::write_field(&mut output, b"Package", package.as_bytes());
+    control::write_field(&mut output, b"Description-md5", md5sum.as_bytes());
+    control::write_field(
+        &mut output,
+        ["Description-", language].concat().as_bytes(),
+        description.as_bytes(),
+    );
+    output
+}
+
+/// Reads each of the translated descriptions of a package, other than English.
+fn translated_descriptions(translated: &Path, package: &str) -> io::Result<Vec<(String, String)>> {
+    let directory = translated.join(package).join("i18n");
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut descriptions = Vec::new();
+    for entry in directory.read_dir()? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let language = match file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("Description-"))
+        {
+            Some(language) if !language.is_empty() && language != "en" => language,
+            _ => continue,
+        };
+
+        let text = misc::read_to_string(entry.path())?;
+        let mut lines = text.trim_end().lines();
+        let mut description = lines.next().unwrap_or("").trim().to_owned();
+        for line in lines {
+            description.push('\n');
+            if line.trim().is_empty() {
+                description.push_str(" .");
+            } else if line.starts_with(' ') {
+                description.push_str(line);
+            } else {
+                description.push(' ');
+                description.push_str(line);
+            }
+        }
+
+        descriptions.push((language.to_owned(), description));
+    }
+
+    descriptions.sort();
+    Ok(descriptions)
+}
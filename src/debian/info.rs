@@ -7,7 +7,9 @@ pub fn get_debian_package_info(package: &Path) -> Option<(String, String)> {
     filename = &filename[underscore_pos + 1..];
     underscore_pos = filename.find('_')?;
     Some((
-        if filename.ends_with("ddeb") {
+        if filename.ends_with("udeb") {
+            [name, "_u"].concat()
+        } else if filename.ends_with("ddeb") {
             [name, "_d"].concat()
         } else {
             name.to_owned()
@@ -34,5 +36,11 @@ mod tests {
             get_debian_package_info(&package),
             Some(("name_d".to_owned(), "version".to_owned()))
         );
+
+        let package = Path::new("/some_/pa_th/to/name_version_arch.udeb");
+        assert_eq!(
+            get_debian_package_info(package),
+            Some(("name_u".to_owned(), "version".to_owned()))
+        );
     }
 }
@@ -1,4 +1,6 @@
+pub mod arch;
 pub mod archive;
+pub mod control;
 pub mod dist_files;
 pub mod info;
 pub mod missing;
@@ -8,12 +10,25 @@ pub use self::info::*;
 pub use self::missing::*;
 
 use crate::compress::*;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 pub const DEB_SOURCE_EXTENSIONS: &[&str] = &[".tar.gz", ".tar.xz", ".tar.zst", ".dsc"];
 pub const DEB_DEBUG_EXTENSION: &str = ".ddeb";
 pub const DEB_EXTENSION: &str = ".deb";
+pub const DEB_INSTALLER_EXTENSION: &str = ".udeb";
+
+/// Where the debug symbols in `.ddeb` archives are indexed in the dists.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugSymbols {
+    /// In the `<component>/debug` component of the suite.
+    #[default]
+    Component,
+    /// In the `<suite>-debug` suite, which has its own Release file.
+    Suite,
+}
 
 pub type Arch = String;
 pub type Component = String;
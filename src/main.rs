@@ -7,6 +7,7 @@ pub mod debian;
 pub mod iter_reader;
 pub mod misc;
 mod repo;
+mod sign;
 pub mod url;
 
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand, crate_authors, crate_version};
@@ -83,6 +84,11 @@ async fn main() {
                 .about("only builds the dist files"))
         ).subcommand(SubCommand::with_name("clean")
             .about("cleans excess packages from the repository")
+        ).subcommand(SubCommand::with_name("dist")
+            .about("Manages the published dist files")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("rollback")
+                .about("restores the dist files that were published before the current dist files"))
         ).subcommand(SubCommand::with_name("config")
             .about("Gets or sets fields within the repo config")
             .alias("c")
@@ -95,6 +101,11 @@ async fn main() {
         ).subcommand(SubCommand::with_name("update")
             .about("Updates direct download-based packages in the configuration")
             .alias("u")
+        ).subcommand(SubCommand::with_name("outdated")
+            .about("Compares the versions of packages in the configuration to their upstream versions")
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("prints the reports as JSON Lines, with a JSON object for each package"))
         ).subcommand(SubCommand::with_name("migrate")
             .about("Moves a package from one component to another, updating both components in the process")
             .alias("m")
@@ -216,12 +227,19 @@ async fn apply_config<'a>(mut config: Config, matches: &ArgMatches<'a>) {
                 exit(1);
             }
         }
+        Action::Outdated(json) => repo::outdated(&config, json).await,
         Action::Pool => {
             Repo::prepare(config, Packages::All).download().await;
         }
         Action::Remove(packages) => {
             Repo::prepare(config, Packages::Select(&packages, false)).remove();
         }
+        Action::Rollback => {
+            if let Err(why) = repo::rollback_release_files(&config) {
+                log::error!("dist rollback failed: {}", why);
+                exit(1);
+            }
+        }
         Action::Update(key, value) => match config.update(key, value.to_owned()) {
             Ok(()) => match config.write_to_disk() {
                 Ok(()) => log::info!("successfully wrote config changes to disk"),
@@ -235,6 +253,23 @@ async fn apply_config<'a>(mut config: Config, matches: &ArgMatches<'a>) {
                 exit(1);
             }
         },
+        Action::UpdateDirect => {
+            let (updated, errors) = repo::update(&mut config).await;
+            if updated != 0 {
+                match config.write_to_disk() {
+                    Ok(()) => log::info!("updated {} package(s) in the config", updated),
+                    Err(why) => {
+                        log::error!("failed to write config changes: {}", why);
+                        exit(1);
+                    }
+                }
+            }
+
+            if !errors.is_empty() {
+                log::error!("exiting due to error(s): {:#?}", errors);
+                exit(1);
+            }
+        }
         Action::UpdateRepository => {
             Repo::prepare(config, Packages::All)
                 .download()
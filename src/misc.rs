@@ -10,10 +10,7 @@ use walkdir::{DirEntry, WalkDir};
 
 pub const INCLUDE_DDEB: u8 = 1;
 pub const INCLUDE_SRCS: u8 = 2;
-
-pub static ARCHITECTURES: [&str; 10] = [
-    "amd64", "i386", "arm64", "armel", "armhf", "mips", "mipsel", "mips64el", "ppc64el", "s390x",
-];
+pub const INCLUDE_UDEB: u8 = 4;
 
 pub fn filename_from_url(url: &str) -> &str {
     &url[url.rfind('/').map_or(0, |x| x + 1)..]
@@ -71,6 +68,13 @@ pub fn is_deb(entry: &DirEntry, flags: u8) -> bool {
                     false
                 }
             }
+            || {
+                if flags & INCLUDE_UDEB != 0 {
+                    e.ends_with(".udeb")
+                } else {
+                    false
+                }
+            }
             || {
                 if flags & INCLUDE_SRCS != 0 {
                     DEB_SOURCE_EXTENSIONS
@@ -83,7 +87,7 @@ pub fn is_deb(entry: &DirEntry, flags: u8) -> bool {
     })
 }
 
-pub fn walk_debs(path: &Path, ddeb: bool) -> Box<dyn Iterator<Item = DirEntry>> {
+pub fn walk_debs(path: &Path, flags: u8) -> Box<dyn Iterator<Item = DirEntry>> {
     Box::new(
         WalkDir::new(path)
             .into_iter()
@@ -91,7 +95,7 @@ pub fn walk_debs(path: &Path, ddeb: bool) -> Box<dyn Iterator<Item = DirEntry>>
                 if e.path().is_dir() {
                     true
                 } else {
-                    is_deb(e, ddeb as u8)
+                    is_deb(e, flags)
                 }
             })
             .flat_map(|e| e.ok()),
@@ -141,15 +145,6 @@ pub fn unlink(link: &Path) -> io::Result<()> {
         })
 }
 
-pub fn get_arch_from_stem(stem: &str) -> &str {
-    if let Some(arch) = ARCHITECTURES.iter().find(|&x| stem.ends_with(x)) {
-        return arch;
-    }
-
-    let arch = &stem[stem.rfind('_').unwrap_or(0) + 1..];
-    arch.find('-').map_or(arch, |pos| &arch[..pos])
-}
-
 // NOTE: The following functions are implemented within Rust's standard in 1.26.0
 
 fn initial_buffer_size(file: &File) -> usize {
@@ -190,3 +185,88 @@ pub async fn fetch(url: &str, file: &mut File) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Atomically exchanges the paths of two files or directories.
+///
+/// File systems which do not support `RENAME_EXCHANGE` fall back to `exchange_by_rename`, which
+/// is not atomic.
+pub fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    let cstring = |path: &Path| {
+        CString::new(path.to_path_buf().into_os_string().into_vec())
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, format!("{}", why)))
+    };
+
+    let (a_path, b_path) = (cstring(a)?, cstring(b)?);
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a_path.as_ptr(),
+            libc::AT_FDCWD,
+            b_path.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+
+    if result == 0 {
+        return Ok(());
+    }
+
+    let why = io::Error::last_os_error();
+    match why.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOSYS) => {
+            log::warn!(
+                "atomic exchange of {} and {} is not supported: {}",
+                a.display(),
+                b.display(),
+                why
+            );
+            exchange_by_rename(a, b)
+        }
+        _ => Err(why),
+    }
+}
+
+/// Exchanges the paths of two files or directories by moving `b` aside, renaming `a` into its
+/// place, and then moving `b` to `a`.
+///
+/// This is not atomic: `b` will briefly not exist, and if this is interrupted, the original `b`
+/// will be left at `<b>.exchange`.
+fn exchange_by_rename(a: &Path, b: &Path) -> io::Result<()> {
+    let mut aside = b.as_os_str().to_owned();
+    aside.push(".exchange");
+    let aside = Path::new(&aside);
+
+    if aside.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} was left by an interrupted exchange, and must be restored or removed",
+                aside.display()
+            ),
+        ));
+    }
+
+    fs::rename(b, aside)?;
+    fs::rename(a, b)?;
+    fs::rename(aside, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exchange_directories_by_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        for (path, name) in [(&a, "a"), (&b, "b")] {
+            fs::create_dir(path).unwrap();
+            fs::write(path.join("file"), name).unwrap();
+        }
+
+        exchange_by_rename(&a, &b).unwrap();
+        assert_eq!(fs::read_to_string(a.join("file")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(b.join("file")).unwrap(), "a");
+        assert!(!dir.path().join("b.exchange").exists());
+    }
+}
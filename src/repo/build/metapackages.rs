@@ -1,12 +1,89 @@
 use super::super::pool::{ARCHIVES_ONLY, mv_to_pool};
 use crate::command::Command;
-use std::env;
+use crate::config::{Config, Metapackage};
+use crate::debian::arch::{self, Architectures};
+use crate::debian::archive;
+use debarchive::Archive as DebArchive;
 use std::fs;
 use std::io::{self, Error, ErrorKind};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
-pub fn generate(suite: &str, component: &str) -> io::Result<()> {
+pub fn generate(config: &Config) -> io::Result<()> {
+    let (suite, component) = (&config.archive, &config.default_component);
+
+    if let Some(ref metapackages) = config.metapackage {
+        let build = PathBuf::from(["build/", suite, "/metapackages"].concat());
+        let maintainer = [config.origin.as_str(), " <", &config.email, ">"].concat();
+        for metapackage in metapackages {
+            build_native(config, metapackage, &build, &maintainer)?;
+        }
+
+        if build.exists() {
+            mv_to_pool(
+                &build,
+                suite,
+                component,
+                &config.arches(),
+                ARCHIVES_ONLY,
+                None,
+            )?;
+        }
+    }
+
+    generate_equivs(suite, component, &config.arches())
+}
+
+/// Builds the metapackage, unless the package in the pool was built from the same definition.
+fn build_native(
+    config: &Config,
+    metapackage: &Metapackage,
+    build: &Path,
+    maintainer: &str,
+) -> io::Result<()> {
+    let name = &metapackage.name;
+    // The epoch of the version is not a part of the file name.
+    let version = metapackage
+        .version
+        .split_once(':')
+        .map_or(metapackage.version.as_str(), |(_, version)| version);
+    let filename = [name, "_", version, "_", &metapackage.arch, ".deb"].concat();
+    let pool = PathBuf::from(
+        [
+            "repo/pool/",
+            &config.archive,
+            "/",
+            &config.default_component,
+            "/",
+            &arch::binary_dir(&metapackage.arch),
+            "/",
+            &name[..1],
+            "/",
+            name,
+            "/",
+            &filename,
+        ]
+        .concat(),
+    );
+
+    let fields = metapackage.fields(maintainer);
+
+    // Every field in the pool besides the Installed-Size is written from the definition.
+    if pool.exists() {
+        let mut pooled = archive::control_fields(&DebArchive::new(&pool)?)?;
+        pooled.retain(|(key, _)| !key.eq_ignore_ascii_case("Installed-Size"));
+        if pooled == fields {
+            log::info!("metapackage {} is up to date", name);
+            return Ok(());
+        }
+    }
+
+    log::info!("generating metapackage {}", name);
+    archive::build(&build.join(&filename), &fields, config.deb_compression)
+}
+
+/// Generates metapackages from the equivs `.cfg` files in `metapackages/<suite>`.
+fn generate_equivs(suite: &str, component: &str, architectures: &Architectures) -> io::Result<()> {
     let metapackages = &Path::new("metapackages").join(suite);
     if !metapackages.exists() {
         return Ok(());
@@ -41,7 +118,14 @@ pub fn generate(suite: &str, component: &str) -> io::Result<()> {
         })
         .collect::<io::Result<()>>()?;
 
-    mv_to_pool(&metapackages, suite, component, ARCHIVES_ONLY, None)
+    mv_to_pool(
+        metapackages,
+        suite,
+        component,
+        architectures,
+        ARCHIVES_ONLY,
+        None,
+    )
 }
 
 fn is_cfg(entry: &DirEntry) -> bool {
@@ -64,15 +148,8 @@ fn inner_generate(entry: &DirEntry) -> io::Result<()> {
         )
     })?;
 
-    directory_scope(parent, move || {
-        Command::new("equivs-build").arg(filename).run()
-    })
-}
-
-pub fn directory_scope<T, F: FnMut() -> io::Result<T>>(path: &Path, mut scope: F) -> io::Result<T> {
-    let previous = env::current_dir()?;
-    env::set_current_dir(path)?;
-    let result = scope()?;
-    env::set_current_dir(previous)?;
-    Ok(result)
+    Command::new("equivs-build")
+        .arg(filename)
+        .current_dir(parent)
+        .run()
 }
@@ -9,11 +9,14 @@ use super::super::SHARED_ASSETS;
 use super::pool::{KEEP_SOURCE, mv_to_pool};
 use super::version::{changelog, git};
 use crate::command::Command;
-use crate::config::{Config, DebianPath, Direct, Source, SourceLocation};
-use crate::debian;
+use crate::config::{Config, DebianPath, Direct, Repackage, Source, SourceLocation};
+use crate::debian::arch::Architectures;
+use crate::debian::{
+    self,
+    archive::{self, DebCompression},
+};
 use crate::misc;
 use deb_version;
-use debarchive::Archive as DebArchive;
 use glob::glob;
 use std::cmp::Ordering;
 use std::env;
@@ -42,6 +45,7 @@ pub fn all(config: &Config) {
                 &build_path,
                 &config.archive,
                 &config.default_component,
+                &config.arches(),
                 if source.keep_source { KEEP_SOURCE } else { 0 },
                 Some(&source.name),
             ) {
@@ -55,12 +59,19 @@ pub fn all(config: &Config) {
         }
     }
 
-    if let Err(why) = repackage_binaries(config.direct.as_ref(), suite, component) {
+    if let Err(why) = repackage_binaries(
+        config.direct.as_ref(),
+        &config.path,
+        suite,
+        component,
+        &config.arches(),
+        config.deb_compression,
+    ) {
         log::error!("binary repackage failure: {}", why);
         exit(1);
     }
 
-    if let Err(why) = metapackages::generate(&config.archive, &config.default_component) {
+    if let Err(why) = metapackages::generate(config) {
         log::error!("metapackage generation failed: {}", why);
         exit(1);
     }
@@ -95,6 +106,7 @@ pub fn packages(config: &Config, packages: &[&str], force: bool) {
                     &build_path,
                     &config.archive,
                     &config.default_component,
+                    &config.arches(),
                     if source.keep_source { KEEP_SOURCE } else { 0 },
                     Some(&source.name),
                 ) {
@@ -118,16 +130,28 @@ pub fn packages(config: &Config, packages: &[&str], force: bool) {
 
 fn repackage_binaries(
     packages: Option<&Vec<Direct>>,
+    config_path: &Path,
     suite: &str,
     component: &str,
+    architectures: &Architectures,
+    compression: DebCompression,
 ) -> io::Result<()> {
     if let Some(packages) = packages {
         for package in packages {
-            for destinations in package.get_destinations(suite, component).unwrap() {
+            for destinations in package
+                .get_destinations(suite, component, architectures)
+                .unwrap()
+            {
                 let pool = &destinations.pool;
                 if let Some(&(ref files, ref source_deb)) = destinations.assets.as_ref() {
-                    if needs_to_repackage(source_deb, files, pool)? {
-                        repackage(source_deb, files, pool)?;
+                    if needs_to_repackage(source_deb, files, config_path, pool)? {
+                        repackage(
+                            source_deb,
+                            files,
+                            package.repackage.as_ref(),
+                            compression,
+                            pool,
+                        )?;
                     }
                 }
             }
@@ -137,14 +161,24 @@ fn repackage_binaries(
     Ok(())
 }
 
-/// If source binary exists, and the files to replace are newer than the file in the pool, repackage.
-fn needs_to_repackage(source: &Path, replace: &Path, pool: &Path) -> io::Result<bool> {
+/// If source binary exists, and the files to replace or the config are newer than the file in
+/// the pool, repackage.
+fn needs_to_repackage(
+    source: &Path,
+    replace: &Path,
+    config: &Path,
+    pool: &Path,
+) -> io::Result<bool> {
     log::info!("checking if {:?} needs to be repackaged", pool);
-    if !pool.exists() || !source.exists() || !replace.exists() {
+    if !pool.exists() || !source.exists() {
         return Ok(true);
     }
 
     let timestamp_in_pool = pool.metadata()?.modified()?;
+    if config.metadata()?.modified()? > timestamp_in_pool {
+        return Ok(true);
+    }
+
     for entry in WalkDir::new(replace).into_iter().flat_map(|e| e.ok()) {
         if entry.metadata()?.modified()? > timestamp_in_pool {
             return Ok(true);
@@ -154,7 +188,13 @@ fn needs_to_repackage(source: &Path, replace: &Path, pool: &Path) -> io::Result<
     Ok(false)
 }
 
-fn repackage(source: &Path, replace: &Path, pool: &Path) -> io::Result<()> {
+fn repackage(
+    source: &Path,
+    replace: &Path,
+    changes: Option<&Repackage>,
+    compression: DebCompression,
+    pool: &Path,
+) -> io::Result<()> {
     log::info!("repackaging {:?}", pool);
 
     log::debug!("source: {:?}", source);
@@ -163,40 +203,26 @@ fn repackage(source: &Path, replace: &Path, pool: &Path) -> io::Result<()> {
     let data_replace = replace.join("data");
     let control_replace = replace.join("DEBIAN");
 
-    if !control_replace.exists() {
-        fs::create_dir_all(&control_replace)?;
-    }
-
-    let parent = source.parent().unwrap();
-    let data_dir = parent.join("data");
-    let control_dir = parent.join("data/DEBIAN");
-
-    if control_dir.exists() {
-        fs::remove_dir_all(&control_dir)?;
-    }
-
-    if data_dir.exists() {
-        fs::remove_dir_all(&data_dir)?;
-    }
-
-    fs::create_dir_all(&control_dir)?;
-
-    let archive = DebArchive::new(source)?;
-    archive.data_extract(&data_dir)?;
-    archive.control_extract(&control_dir)?;
-
-    if data_replace.exists() {
-        rsync(&data_replace, &parent)?;
-    }
-
-    if control_replace.exists() {
-        rsync(&control_replace, &data_dir)?;
-    }
+    let exclude = changes
+        .map_or(&[][..], |changes| &changes.remove_files)
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern.trim_start_matches(['.', '/']))
+                .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
 
-    fs::create_dir_all(pool.parent().unwrap())?;
-    debian::archive::build(&data_dir, pool)?;
+    let overrides = archive::Overrides {
+        data: Some(data_replace.as_path()).filter(|path| path.exists()),
+        control: Some(control_replace.as_path()).filter(|path| path.exists()),
+        exclude: &exclude,
+    };
 
-    Ok(())
+    archive::repackage(source, pool, &overrides, compression, |fields| {
+        if let Some(changes) = changes {
+            changes.apply(fields);
+        }
+    })
 }
 
 fn migrate_to_pool<'a, I: Iterator<Item = &'a Source>>(config: &Config, sources: I) {
@@ -206,6 +232,7 @@ fn migrate_to_pool<'a, I: Iterator<Item = &'a Source>>(config: &Config, sources:
             &build_path,
             &config.archive,
             &config.default_component,
+            &config.arches(),
             if source.keep_source { KEEP_SOURCE } else { 0 },
             Some(&source.name),
         ) {
@@ -624,8 +651,8 @@ fn pre_flight(
     };
 
     config
-        .architectures
-        .iter()
+        .arches()
+        .configured()
         .try_for_each(|arch| sbuild(config, item, &pwd, suite, component, dir, arch))?;
 
     let result = match record {
@@ -687,7 +714,7 @@ fn sbuild<P: AsRef<Path>>(
 
     if let Some(ref depends) = item.depends {
         let pool = pwd.join(&["repo/pool/", suite, "/", component].concat());
-        let deb_iter = misc::walk_debs(&pool, false).flat_map(|deb| misc::match_deb(&deb, depends));
+        let deb_iter = misc::walk_debs(&pool, 0).flat_map(|deb| misc::match_deb(&deb, depends));
 
         let mut temp: Vec<(String, usize, String, String)> = Vec::new();
         for (deb, pos) in deb_iter {
@@ -0,0 +1,117 @@
+use crate::checksum::Digests;
+use crate::debian::AppStreamFiles;
+use crate::debian::control::Fields;
+use crate::misc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// What is read from a deb archive to generate its entries in the dist files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DebMetadata {
+    pub control: Fields,
+    pub files: Vec<PathBuf>,
+    pub size: u64,
+    pub digests: Digests,
+    pub appstream: AppStreamFiles,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheEntry {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    inode: u64,
+    metadata: DebMetadata,
+}
+
+impl CacheEntry {
+    fn is_current(&self, file: &Metadata) -> bool {
+        self.size == file.size()
+            && self.mtime == file.mtime()
+            && self.mtime_nsec == file.mtime_nsec()
+            && self.inode == file.ino()
+    }
+}
+
+/// Stores the metadata of each deb archive in the pool between runs, so that only the archives
+/// which are new or have changed need to be read again.
+pub struct DebCache {
+    path: PathBuf,
+    previous: HashMap<PathBuf, CacheEntry>,
+    current: Mutex<HashMap<PathBuf, CacheEntry>>,
+}
+
+impl DebCache {
+    /// Loads the cache from the last run. A cache which cannot be read is discarded.
+    pub fn load(path: PathBuf) -> Self {
+        let previous = if path.exists() {
+            misc::read(&path)
+                .and_then(|data| serde_json::from_slice(&data).map_err(io::Error::other))
+                .unwrap_or_else(|why| {
+                    log::warn!("discarding deb cache at {}: {}", path.display(), why);
+                    HashMap::new()
+                })
+        } else {
+            HashMap::new()
+        };
+
+        DebCache {
+            path,
+            previous,
+            current: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the metadata of the deb archive from the cache, or with `read` if the archive is
+    /// not in the cache, or it has changed since it was cached.
+    pub fn metadata<F>(&self, deb: &Path, read: F) -> io::Result<DebMetadata>
+    where
+        F: FnOnce(&Path) -> io::Result<DebMetadata>,
+    {
+        let file = fs::metadata(deb)?;
+        let metadata = match self.previous.get(deb) {
+            Some(entry) if entry.is_current(&file) => entry.metadata.clone(),
+            _ => read(deb)?,
+        };
+
+        let entry = CacheEntry {
+            size: file.size(),
+            mtime: file.mtime(),
+            mtime_nsec: file.mtime_nsec(),
+            inode: file.ino(),
+            metadata: metadata.clone(),
+        };
+
+        self.current
+            .lock()
+            .map_err(|_| io::Error::other("deb cache was poisoned"))?
+            .insert(deb.to_path_buf(), entry);
+
+        Ok(metadata)
+    }
+
+    /// Writes the metadata of the deb archives which were seen in this run to the cache.
+    pub fn save(self) -> io::Result<()> {
+        let current = self
+            .current
+            .into_inner()
+            .map_err(|_| io::Error::other("deb cache was poisoned"))?;
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // The cache is replaced atomically, so an interrupted write will not corrupt it.
+        let temporary = self.path.with_extension("json.tmp");
+        fs::write(
+            &temporary,
+            serde_json::to_vec(&current).map_err(io::Error::other)?,
+        )?;
+        fs::rename(&temporary, &self.path)
+    }
+}
@@ -0,0 +1,106 @@
+use super::limit::Limiter;
+use crate::config::ChecksumsFile;
+use crate::misc;
+use crate::sign;
+use crate::url::UrlTokenizer;
+use reqwest::Client;
+
+/// The checksum that a download will be verified with: either the one that is given, or the one
+/// that is listed for the file in the remote checksums file.
+pub async fn expected(
+    client: &Client,
+    limiter: &Limiter,
+    checksum: Option<&str>,
+    checksums: Option<&ChecksumsFile>,
+    name: &str,
+    version: &str,
+    url: &str,
+) -> anyhow::Result<Option<String>> {
+    match (checksum, checksums) {
+        (Some(checksum), _) => Ok(Some(checksum.to_owned())),
+        (None, Some(checksums)) => lookup(client, limiter, checksums, name, version, url)
+            .await
+            .map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Fetches the checksums file, verifies its signature if it has a key, and finds the checksum of
+/// the file at the URL within it.
+async fn lookup(
+    client: &Client,
+    limiter: &Limiter,
+    checksums: &ChecksumsFile,
+    name: &str,
+    version: &str,
+    url: &str,
+) -> anyhow::Result<String> {
+    let finalize = |url: &str| {
+        UrlTokenizer::finalize(url, name, version)
+            .map_err(|text| anyhow::anyhow!("unsupported variable: {}", text))
+    };
+
+    let sums_url = finalize(&checksums.url)?;
+    let sums = fetch(client, limiter, &sums_url).await?;
+
+    match (&checksums.signature, &checksums.key) {
+        (Some(signature), Some(key)) => {
+            let signature = fetch(client, limiter, &finalize(signature)?).await?;
+            sign::verify_detached(&misc::read_to_string(key)?, &signature, &sums)
+                .map_err(|why| anyhow::anyhow!("failed to verify {}: {}", sums_url, why))?;
+        }
+        (Some(_), None) => {
+            return Err(anyhow::anyhow!(
+                "a key is required to verify the signature of {}",
+                sums_url
+            ));
+        }
+        // A key is only given when the checksums are expected to be signed.
+        (None, Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "a signature is required to verify {} with its key",
+                sums_url
+            ));
+        }
+        (None, None) => (),
+    }
+
+    let filename = misc::filename_from_url(url);
+    let sums = String::from_utf8_lossy(&sums);
+    find_digest(&sums, filename)
+        .map(|digest| checksums.algorithm.format(digest))
+        .ok_or_else(|| anyhow::anyhow!("{} is not listed in {}", filename, sums_url))
+}
+
+async fn fetch(client: &Client, limiter: &Limiter, url: &str) -> anyhow::Result<Vec<u8>> {
+    let _permit = limiter.acquire(url).await;
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Finds the digest of the file in the lines of a `sha256sum`-style file, where each digest is
+/// followed by the path of its file, which is prefixed with `*` when it was read as binary.
+fn find_digest<'a>(sums: &'a str, filename: &str) -> Option<&'a str> {
+    sums.lines().find_map(|line| {
+        let (digest, path) = line.trim().split_once(char::is_whitespace)?;
+        let path = path.trim_start().trim_start_matches('*');
+        (misc::filename_from_url(path) == filename).then_some(digest)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_digests() {
+        let sums = "1111  ./pool/foo_1.0_amd64.deb\n\
+                    2222 *foo_1.0_arm64.deb\n\
+                    3333  foo_1.0.orig.tar.gz\n";
+
+        assert_eq!(find_digest(sums, "foo_1.0_amd64.deb"), Some("1111"));
+        assert_eq!(find_digest(sums, "foo_1.0_arm64.deb"), Some("2222"));
+        assert_eq!(find_digest(sums, "foo_1.0.orig.tar.gz"), Some("3333"));
+        assert_eq!(find_digest(sums, "foo_1.0_i386.deb"), None);
+    }
+}
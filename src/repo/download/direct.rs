@@ -1,5 +1,9 @@
+use super::checksums;
+use super::limit::Limiter;
 use super::request::{self, RequestCompare};
 use crate::config::Direct;
+use crate::debian::arch::Architectures;
+use futures_util::future::join_all;
 use reqwest::Client;
 use std::sync::Arc;
 
@@ -11,20 +15,32 @@ pub enum DownloadResult {
 /// Given an item with a URL, download the item if the item does not already exist.
 pub async fn download(
     client: Arc<Client>,
+    limiter: &Limiter,
     item: &Direct,
     suite: &str,
     component: &str,
+    architectures: &Architectures,
 ) -> anyhow::Result<DownloadResult> {
     log::info!("checking if {} needs to be downloaded", item.name);
 
     let mut downloaded = 0;
 
     for (destination, path) in item
-        .get_destinations(suite, component)?
+        .get_destinations(suite, component, architectures)?
         .into_iter()
         .zip(item.urls.iter())
     {
-        let checksum = path.checksum.as_ref().map(|x| x.as_str());
+        let checksum = checksums::expected(
+            &client,
+            limiter,
+            path.checksum.as_deref(),
+            path.checksums.as_ref(),
+            path.name.as_deref().unwrap_or(&item.name),
+            &item.version,
+            &destination.url,
+        )
+        .await?;
+
         // If the file is to be repackaged, store it in the assets directory, else the pool.
         let target = destination
             .assets
@@ -32,9 +48,10 @@ pub async fn download(
             .map_or(&destination.pool, |x| &x.1);
         downloaded += request::file(
             client.clone(),
+            limiter,
             item.name.clone(),
             &destination.url,
-            RequestCompare::Checksum(checksum),
+            RequestCompare::Checksum(checksum.as_deref()),
             target,
         )
         .await?;
@@ -44,18 +61,24 @@ pub async fn download(
     Ok(DownloadResult::Downloaded(downloaded))
 }
 
-/// Downloads pre-built Debian packages
+/// Downloads pre-built Debian packages concurrently, within the limits of the limiter.
 pub async fn download_many(
     items: &[Direct],
     suite: &str,
     component: &str,
+    architectures: &Architectures,
+    limiter: &Limiter,
 ) -> Vec<anyhow::Result<DownloadResult>> {
-    let mut results = Vec::new();
-
     let client = Arc::new(Client::new());
-    for item in items {
-        results.push(download(client.clone(), item, suite, component).await);
-    }
-
-    results
+    join_all(items.iter().map(|item| {
+        download(
+            client.clone(),
+            limiter,
+            item,
+            suite,
+            component,
+            architectures,
+        )
+    }))
+    .await
 }
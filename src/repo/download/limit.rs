@@ -0,0 +1,54 @@
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits how many downloads may run at the same time, in total and from each host.
+pub struct Limiter {
+    jobs: Arc<Semaphore>,
+    jobs_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// Held for the duration of a download, and releases its slots when dropped.
+pub struct Permit {
+    _host: OwnedSemaphorePermit,
+    _job: OwnedSemaphorePermit,
+}
+
+impl Limiter {
+    pub fn new(jobs: usize, jobs_per_host: usize) -> Self {
+        Limiter {
+            jobs: Arc::new(Semaphore::new(jobs.max(1))),
+            jobs_per_host: jobs_per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until the URL may be downloaded from.
+    pub async fn acquire(&self, url: &str) -> Permit {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+
+        let host = self
+            .hosts
+            .lock()
+            .expect("download limiter was poisoned")
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.jobs_per_host)))
+            .clone();
+
+        // The host is waited on first, so that a busy host does not occupy the slots of others.
+        let _host = host.acquire_owned().await.expect("semaphore was closed");
+        let _job = self
+            .jobs
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore was closed");
+
+        Permit { _host, _job }
+    }
+}
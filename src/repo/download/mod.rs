@@ -1,9 +1,12 @@
+mod checksums;
 mod direct;
+mod limit;
 mod repos;
 mod request;
 mod sources;
 
 use self::direct::DownloadResult;
+pub(super) use self::limit::Limiter;
 use crate::config::Config;
 use reqwest::{self, Client};
 use std::io;
@@ -13,13 +16,23 @@ use std::sync::Arc;
 
 pub async fn all(config: &Config) {
     let mut errors = Vec::new();
+    let limiter = Arc::new(Limiter::new(
+        config.download_jobs,
+        config.download_jobs_per_host,
+    ));
 
     if let Some(ref ddl_sources) = config.direct {
-        for (id, result) in
-            direct::download_many(ddl_sources, &config.archive, &config.default_component)
-                .await
-                .into_iter()
-                .enumerate()
+        let architectures = config.arches();
+        for (id, result) in direct::download_many(
+            ddl_sources,
+            &config.archive,
+            &config.default_component,
+            &architectures,
+            &limiter,
+        )
+        .await
+        .into_iter()
+        .enumerate()
         {
             let name = &ddl_sources[id].name;
             match result {
@@ -36,7 +49,7 @@ pub async fn all(config: &Config) {
     }
 
     if let Some(ref sources) = config.source {
-        for (id, result) in sources::download_many(sources, &config.archive)
+        for (id, result) in sources::download_many(sources, &config.archive, &limiter)
             .await
             .into_iter()
             .enumerate()
@@ -60,6 +73,7 @@ pub async fn all(config: &Config) {
             repos,
             config.archive.clone(),
             config.default_component.clone(),
+            limiter.clone(),
         )
         .await
         {
@@ -86,17 +100,21 @@ pub async fn all(config: &Config) {
 pub async fn packages(sources: &Config, packages: &[&str]) {
     let mut downloaded = 0;
     let client = Arc::new(Client::new());
+    let limiter = Limiter::new(sources.download_jobs, sources.download_jobs_per_host);
 
     if let Some(ref source) = sources.direct.as_ref() {
+        let architectures = sources.arches();
         for source in source
             .iter()
             .filter(|s| packages.contains(&s.name.as_str()))
         {
             if let Err(why) = direct::download(
                 client.clone(),
+                &limiter,
                 source,
                 &sources.archive,
                 &sources.default_component,
+                &architectures,
             )
             .await
             {
@@ -116,7 +134,7 @@ pub async fn packages(sources: &Config, packages: &[&str]) {
             .iter()
             .filter(|s| packages.contains(&s.name.as_str()))
         {
-            if let Err(why) = sources::download(source, &sources.archive).await {
+            if let Err(why) = sources::download(source, &sources.archive, &limiter).await {
                 log::error!("failed to download source {}: {}", &source.name, why);
                 exit(1);
             }
@@ -144,6 +162,8 @@ pub enum DownloadError {
         expected: String,
         received: String,
     },
+    #[error("no checksum was given for {}", name)]
+    ChecksumMissing { name: String },
     #[error("failed to fetch remote files via dget for {}: {}", url, why)]
     DGet { url: String, why: io::Error },
     #[error("git exited with an error: {}", why)]
@@ -1,3 +1,4 @@
+use super::limit::Limiter;
 use super::request::{self, RequestCompare};
 use crate::config::Repo;
 use crate::debian::gen_filename;
@@ -9,7 +10,12 @@ use std::cmp::Ordering;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-pub async fn download(repos: Vec<Repo>, suite: String, component: String) -> anyhow::Result<()> {
+pub async fn download(
+    repos: Vec<Repo>,
+    suite: String,
+    component: String,
+    limiter: Arc<Limiter>,
+) -> anyhow::Result<()> {
     let (in_tx, in_rx) = bounded::<AptEntry>(64);
     let (out_tx, out_rx) = bounded::<(String, String, RequestCompare, PathBuf)>(64);
 
@@ -90,11 +96,27 @@ pub async fn download(repos: Vec<Repo>, suite: String, component: String) -> any
     });
 
     let client = Arc::new(Client::new());
+    let mut tasks = Vec::new();
     for (name, url, compare, dest) in out_rx {
-        request::file(client.clone(), name, &url, compare, &dest).await?;
+        let (client, limiter) = (client.clone(), limiter.clone());
+        tasks.push(tokio::spawn(async move {
+            request::file(client, &limiter, name, &url, compare, &dest).await
+        }));
     }
 
-    Ok(())
+    // Every download is awaited before the first error is returned.
+    let mut result = Ok(());
+    for task in tasks {
+        let outcome = task.await.map_err(anyhow::Error::from).and_then(|r| r);
+        if let Err(why) = outcome {
+            log::error!("{}", why);
+            if result.is_ok() {
+                result = Err(why);
+            }
+        }
+    }
+
+    result
 }
 
 fn get_destination(desc: AptPackage, suite: &str, component: &str) -> PathBuf {
@@ -108,6 +130,8 @@ fn get_destination(desc: AptPackage, suite: &str, component: &str) -> PathBuf {
         &desc.name[..desc.name.len() - 4]
     } else if desc.name.ends_with("-dbgsym") {
         &desc.name[..desc.name.len() - 7]
+    } else if desc.name.ends_with("-udeb") {
+        &desc.name[..desc.name.len() - 5]
     } else {
         &desc.name
     };
@@ -1,11 +1,14 @@
-use crate::checksum::hasher;
-use reqwest::Client;
-use sha2::Sha256;
+use super::limit::Limiter;
+use crate::checksum::Checksum;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Response, StatusCode};
+use std::ffi::OsString;
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{self, Write};
 use std::os::unix::fs::MetadataExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 const ATTEMPTS: u8 = 3;
 
@@ -14,91 +17,343 @@ pub enum RequestCompare<'a> {
     SizeAndModification(u64, Option<i64>),
 }
 
+/// Downloads the file at the URL to the path, unless the file at the path is already valid.
+///
+/// The file is downloaded to `<path>.part`, and is only moved to the path after it has been
+/// verified. An interrupted download is resumed from the partial file when the server supports
+/// range requests, and the remote file has not changed since the partial file was written.
+/// Failed attempts are retried with an exponential backoff.
 pub async fn file<'a>(
     client: Arc<Client>,
+    limiter: &Limiter,
     _name: String,
     url: &str,
     compare: RequestCompare<'a>,
     path: &Path,
 ) -> anyhow::Result<u64> {
+    // An invalid checksum is reported before anything is downloaded.
+    if let RequestCompare::Checksum(Some(checksum)) = compare {
+        Checksum::parse(checksum)?;
+    }
+
+    if path.exists() && !requires_download(path, &compare)? {
+        return Ok(0);
+    }
+
+    if let Some(parent) = path.parent()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let partial = partial_path(path);
     let mut tries = 0;
 
     loop {
-        let mut file = if path.exists() {
-            let mut requires_download = true;
+        let result = {
+            let _permit = limiter.acquire(url).await;
+            fetch(&client, url, &partial).await
+        };
 
-            match compare {
-                RequestCompare::Checksum(Some(checksum)) => {
-                    let digest = hasher::<Sha256, File>(File::open(path)?)?;
-                    requires_download = digest != checksum;
-                }
-                RequestCompare::SizeAndModification(length, mtime) => {
-                    let file = File::open(path)?;
-                    let metadata = file.metadata()?;
-                    if metadata.len() == length {
-                        if let Some(modified) = mtime {
-                            if modified == metadata.mtime() {
-                                requires_download = false;
-                            }
-                        } else {
-                            requires_download = false;
-                        }
+        let why = match result {
+            Ok((bytes, length)) => match verify(&partial, &compare, length) {
+                Ok(()) => {
+                    fs::rename(&partial, path)?;
+                    remove_validator(&partial)?;
+                    if let RequestCompare::SizeAndModification(_, Some(mtime)) = compare {
+                        let (atime, _) = utime::get_file_times(path)?;
+                        utime::set_file_times(path, atime, mtime)?;
                     }
+
+                    log::info!("finished downloading {}", path.display());
+                    return Ok(bytes);
                 }
-                _ => (),
-            }
+                Err(why) => {
+                    log::error!("{}, removing {}", why, partial.display());
+                    fs::remove_file(&partial)?;
+                    remove_validator(&partial)?;
+                    why
+                }
+            },
+            // The partial file is kept, so that the next attempt may resume from it.
+            Err(why) => why,
+        };
 
-            if !requires_download {
-                return Ok(0);
-            }
+        if tries == ATTEMPTS {
+            return Err(why);
+        }
 
-            fs::OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(path)?
-        } else {
-            if let Some(parent) = path.parent() {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)?;
-                }
+        tries += 1;
+        let delay = Duration::from_secs(1 << tries);
+        log::warn!(
+            "failed to download {}: {}. retrying in {} seconds",
+            url,
+            why,
+            delay.as_secs()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Whether the existing file at the path differs from the remote file.
+fn requires_download(path: &Path, compare: &RequestCompare) -> anyhow::Result<bool> {
+    let requires_download = match *compare {
+        RequestCompare::Checksum(Some(checksum)) => {
+            let checksum = Checksum::parse(checksum)?;
+            !checksum.matches(&checksum.digest_of(path)?)
+        }
+        RequestCompare::SizeAndModification(length, mtime) => {
+            let metadata = fs::metadata(path)?;
+            metadata.len() != length || mtime.is_some_and(|mtime| mtime != metadata.mtime())
+        }
+        RequestCompare::Checksum(None) => true,
+    };
+
+    Ok(requires_download)
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut filename = path.file_name().map_or_else(OsString::new, OsString::from);
+    filename.push(".part");
+    path.with_file_name(filename)
+}
+
+/// Where the validator of the remote file that the partial file was downloaded from is kept.
+fn validator_path(partial: &Path) -> PathBuf {
+    let mut filename = partial
+        .file_name()
+        .map_or_else(OsString::new, OsString::from);
+    filename.push(".validator");
+    partial.with_file_name(filename)
+}
+
+fn remove_validator(partial: &Path) -> io::Result<()> {
+    match fs::remove_file(validator_path(partial)) {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why),
+        _ => Ok(()),
+    }
+}
+
+/// The strong ETag of the response, or its Last-Modified date, which identify the version of
+/// the remote file for `If-Range`. Weak ETags may not be used for ranges.
+fn validator(response: &Response) -> Option<String> {
+    let headers = response.headers();
+    headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| headers.get(LAST_MODIFIED)?.to_str().ok())
+        .map(String::from)
+}
+
+/// Downloads the remainder of the file to the partial path, returning how many bytes were
+/// received, and the length of the complete file if the server reported it.
+///
+/// A partial file is only resumed with the validator of the remote file it was downloaded
+/// from, so that the server will send the whole file instead if it has since changed.
+async fn fetch(client: &Client, url: &str, partial: &Path) -> anyhow::Result<(u64, Option<u64>)> {
+    let previous = fs::read_to_string(validator_path(partial)).ok();
+    let offset = match previous {
+        Some(_) => fs::metadata(partial).map_or(0, |metadata| metadata.len()),
+        None => 0,
+    };
+
+    let mut request = client.get(url);
+    if let Some(ref previous) = previous
+        && offset != 0
+    {
+        request = request
+            .header(RANGE, format!("bytes={}-", offset))
+            .header(IF_RANGE, previous.as_str());
+    }
+
+    let mut response = request.send().await?;
+
+    let (mut file, length) = match response.status() {
+        StatusCode::PARTIAL_CONTENT => match content_range(&response) {
+            // Servers which ignore `If-Range` may send a range of a file which has changed.
+            Some((start, length)) if start == offset && validator(&response) == previous => {
+                log::info!("resuming download of {} from {} bytes", url, offset);
+                (fs::OpenOptions::new().append(true).open(partial)?, length)
+            }
+            _ => {
+                fs::remove_file(partial)?;
+                remove_validator(partial)?;
+                return Err(anyhow::anyhow!(
+                    "server did not resume {} from {} bytes",
+                    url,
+                    offset
+                ));
+            }
+        },
+        // The partial file already has every byte of the remote file.
+        StatusCode::RANGE_NOT_SATISFIABLE if offset != 0 => {
+            return Ok((0, content_range(&response).and_then(|(_, length)| length)));
+        }
+        _ => {
+            response = response.error_for_status()?;
+            log::info!("downloading {} to {}", url, partial.display());
+            let file = File::create(partial)?;
+            match validator(&response) {
+                Some(validator) => fs::write(validator_path(partial), validator)?,
+                None => remove_validator(partial)?,
             }
-            File::create(path)?
-        };
 
-        log::info!("downloading package to {}", path.display());
+            (file, response.content_length())
+        }
+    };
 
-        let mut response = client.get(url).send().await?;
+    let mut bytes = 0;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        bytes += chunk.len() as u64;
+    }
 
-        while let Some(chunk) = response.chunk().await? {
-            file.write(&chunk)?;
+    file.flush()?;
+    Ok((bytes, length))
+}
+
+/// The first byte and the complete length of a `Content-Range` header, such as
+/// `bytes 100-199/200`, or `bytes */200` when the range is not satisfiable.
+fn content_range(response: &Response) -> Option<(u64, Option<u64>)> {
+    let range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, length) = range.strip_prefix("bytes ")?.split_once('/')?;
+    let start = match range {
+        "*" => 0,
+        range => range.split_once('-')?.0.parse().ok()?,
+    };
+
+    Some((start, length.parse().ok()))
+}
+
+/// Checks that the downloaded file has the expected checksum and length.
+fn verify(partial: &Path, compare: &RequestCompare, length: Option<u64>) -> anyhow::Result<()> {
+    let received = fs::metadata(partial)?.len();
+    let expected = match *compare {
+        RequestCompare::SizeAndModification(length, _) => Some(length),
+        RequestCompare::Checksum(_) => length,
+    };
+
+    if let Some(expected) = expected
+        && received != expected
+    {
+        return Err(anyhow::anyhow!(
+            "expected {} bytes for {}, but received {}",
+            expected,
+            partial.display(),
+            received
+        ));
+    }
+
+    if let RequestCompare::Checksum(Some(checksum)) = *compare {
+        let checksum = Checksum::parse(checksum)?;
+        if !checksum.matches(&checksum.digest_of(partial)?) {
+            return Err(anyhow::anyhow!(
+                "checksum does not match for {}",
+                partial.display()
+            ));
         }
+    }
 
-        file.flush()?;
-
-        log::info!("finished downloading {}", path.display());
-        if let RequestCompare::Checksum(Some(checksum)) = compare {
-            let digest = hasher::<Sha256, File>(File::open(path)?)?;
-            if digest == checksum {
-                return Ok(0);
-            } else {
-                log::error!("checksum does not match for {}, removing.", path.display());
-                fs::remove_file(&path)?;
-
-                if tries == ATTEMPTS {
-                    return Err(anyhow::anyhow!(
-                        "checksum does not match for {}",
-                        path.display()
-                    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    /// Serves `body` with the `etag` for each connection, honoring `Range` only when the
+    /// `If-Range` of the request matches the ETag.
+    fn serve(body: &'static [u8], etag: &'static str, requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let (mut range, mut if_range) = (None, None);
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+
+                    let (name, value) = line.split_once(": ").unwrap_or((&line, ""));
+                    match name.to_ascii_lowercase().as_str() {
+                        "range" => range = value.strip_prefix("bytes=").map(String::from),
+                        "if-range" => if_range = Some(value.to_owned()),
+                        _ => (),
+                    }
                 }
 
-                tries += 1;
+                let start = match (range, if_range) {
+                    (Some(range), Some(if_range)) if if_range == etag => {
+                        range.trim_end_matches('-').parse::<usize>().unwrap()
+                    }
+                    _ => 0,
+                };
+
+                let status = if start == 0 {
+                    "200 OK".to_owned()
+                } else {
+                    format!(
+                        "206 Partial Content\r\nContent-Range: bytes {}-{}/{}",
+                        start,
+                        body.len() - 1,
+                        body.len()
+                    )
+                };
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    etag,
+                    body.len() - start
+                )
+                .unwrap();
+                stream.write_all(&body[start..]).unwrap();
             }
-        } else if let RequestCompare::SizeAndModification(_length, Some(mtime)) = compare {
-            let (atime, _) = utime::get_file_times(path)?;
-            utime::set_file_times(path, atime, mtime)?;
-            return Ok(0);
-        } else {
-            return Ok(0);
-        }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn partial_resumes_only_unchanged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let partial = dir.path().join("file.part");
+        let client = Client::new();
+
+        // The remote file is unchanged, so only the remainder is received.
+        let url = serve(b"0123456789", "\"a\"", 1);
+        fs::write(&partial, "01234").unwrap();
+        fs::write(validator_path(&partial), "\"a\"").unwrap();
+        assert_eq!(fetch(&client, &url, &partial).await.unwrap(), (5, Some(10)));
+        assert_eq!(fs::read(&partial).unwrap(), b"0123456789");
+
+        // The remote file has changed, so the partial file is replaced.
+        let url = serve(b"abcdefghij", "\"b\"", 1);
+        fs::write(&partial, "01234").unwrap();
+        assert_eq!(
+            fetch(&client, &url, &partial).await.unwrap(),
+            (10, Some(10))
+        );
+        assert_eq!(fs::read(&partial).unwrap(), b"abcdefghij");
+        assert_eq!(
+            fs::read_to_string(validator_path(&partial)).unwrap(),
+            "\"b\""
+        );
+
+        // Without a validator, the partial file cannot be trusted.
+        remove_validator(&partial).unwrap();
+        let url = serve(b"abcdefghij", "\"b\"", 1);
+        fs::write(&partial, "01234").unwrap();
+        assert_eq!(
+            fetch(&client, &url, &partial).await.unwrap(),
+            (10, Some(10))
+        );
+        assert_eq!(fs::read(&partial).unwrap(), b"abcdefghij");
     }
 }
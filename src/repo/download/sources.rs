@@ -1,61 +1,105 @@
 use super::DownloadError;
-use crate::checksum::hasher;
+use super::checksums;
+use super::limit::Limiter;
+use crate::checksum::Checksum;
 use crate::command::Command;
-use crate::config::{Source, SourceLocation};
-use sha2::Sha256;
+use crate::config::{ChecksumsFile, Source, SourceLocation};
+use futures_util::future::join_all;
+use reqwest::Client;
 use std::fs::{self, File};
 use std::path::PathBuf;
 use std::{env, io};
 
-/// Downloads many source repositories
+/// Downloads many source repositories concurrently. Git and dsc sources are fetched by
+/// commands, which are run on threads of their own.
 pub async fn download_many<'a>(
     items: &'a [Source],
     suite: &'a str,
+    limiter: &'a Limiter,
 ) -> Vec<Result<(), DownloadError>> {
-    let mut results = Vec::new();
-
-    for item in items {
-        results.push(download(item, suite).await);
-    }
-
-    results
+    join_all(items.iter().map(|item| download(item, suite, limiter))).await
 }
 
-pub async fn download(item: &Source, suite: &str) -> Result<(), DownloadError> {
+pub async fn download(item: &Source, suite: &str, limiter: &Limiter) -> Result<(), DownloadError> {
     match item.location {
         Some(SourceLocation::Git {
             ref git,
             ref branch,
             ref commit,
-        }) => download_git(&item.name, git, suite, branch, commit)
-            .map_err(|why| DownloadError::GitFailed { why }),
+        }) => {
+            let _permit = limiter.acquire(git).await;
+            let (name, git, suite) = (item.name.clone(), git.clone(), suite.to_owned());
+            let (branch, commit) = (branch.clone(), commit.clone());
+            blocking(move || download_git(&name, &git, &suite, &branch, &commit))
+                .await
+                .map_err(|why| DownloadError::GitFailed { why })
+        }
         Some(SourceLocation::URL {
             ref url,
             ref checksum,
-        }) => download_(item, url, checksum).await,
+            ref checksums,
+        }) => download_(item, url, checksum.as_deref(), checksums.as_ref(), limiter).await,
         Some(SourceLocation::Dsc { ref dsc }) => {
-            download_dsc(item, dsc, suite).map_err(|why| DownloadError::DGet {
-                url: dsc.to_owned(),
-                why,
-            })
+            let _permit = limiter.acquire(dsc).await;
+            let (name, url, suite) = (item.name.clone(), dsc.clone(), suite.to_owned());
+            blocking(move || download_dsc(&name, &url, &suite))
+                .await
+                .map_err(|why| DownloadError::DGet {
+                    url: dsc.to_owned(),
+                    why,
+                })
         }
         None => Ok(()),
     }
 }
 
-async fn download_(item: &Source, url: &str, checksum: &str) -> Result<(), DownloadError> {
+/// Runs a download which blocks, without blocking the downloads which are running beside it.
+async fn blocking<F>(download: F) -> io::Result<()>
+where
+    F: FnOnce() -> io::Result<()> + Send + 'static,
+{
+    tokio::task::spawn_blocking(download)
+        .await
+        .map_err(io::Error::other)?
+}
+
+async fn download_(
+    item: &Source,
+    url: &str,
+    checksum: Option<&str>,
+    checksums: Option<&ChecksumsFile>,
+    limiter: &Limiter,
+) -> Result<(), DownloadError> {
     let filename = &url[url.rfind('/').map_or(0, |x| x + 1)..];
     let destination = PathBuf::from(["assets/cache/", &item.name, "_", &filename].concat());
 
+    let request_error = |why| DownloadError::Request {
+        name: filename.to_owned(),
+        why,
+    };
+
+    let version = item.version.as_deref().unwrap_or_default();
+    let client = Client::new();
+    let expected = checksums::expected(
+        &client, limiter, checksum, checksums, &item.name, version, url,
+    )
+    .await
+    .map_err(request_error)?
+    .ok_or_else(|| DownloadError::ChecksumMissing {
+        name: item.name.clone(),
+    })?;
+
+    let checksum = Checksum::parse(&expected).map_err(|why| request_error(why.into()))?;
+
     let requires_download = if destination.is_file() {
-        let digest = File::open(&destination)
-            .and_then(hasher::<Sha256, File>)
+        let digest = checksum
+            .digest_of(&destination)
             .map_err(|why| DownloadError::Open {
                 file: destination.clone(),
                 why,
             })?;
 
-        digest != checksum
+        !checksum.matches(&digest)
     } else {
         true
     };
@@ -71,28 +115,26 @@ async fn download_(item: &Source, url: &str, checksum: &str) -> Result<(), Downl
             why,
         })?;
 
+        let _permit = limiter.acquire(url).await;
         crate::misc::fetch(url, &mut file)
             .await
-            .map_err(|why| DownloadError::Request {
-                name: filename.to_owned(),
-                why,
-            })?;
+            .map_err(request_error)?;
     }
 
-    let digest = File::open(&destination)
-        .and_then(hasher::<Sha256, File>)
+    let digest = checksum
+        .digest_of(&destination)
         .map_err(|why| DownloadError::Open {
             file: destination.clone(),
             why,
         })?;
 
-    if digest == checksum {
+    if checksum.matches(&digest) {
         Ok(())
     } else {
         let _ = fs::remove_file(&destination);
         Err(DownloadError::ChecksumInvalid {
             name: item.name.clone(),
-            expected: checksum.to_owned(),
+            expected: checksum.digest.to_owned(),
             received: digest,
         })
     }
@@ -196,19 +238,15 @@ fn download_git(
 ///
 /// - The files will only be downloaded, not extracted.
 /// - The files will only be downloaded if they do not already exist.
-fn download_dsc(item: &Source, dsc: &str, suite: &str) -> io::Result<()> {
-    let path = PathBuf::from(["build/", suite, "/", &item.name].concat());
-    let mut result = Ok(());
-    if !path.join(crate::misc::filename_from_url(dsc)).exists() {
-        fs::create_dir_all(&path)?;
-        let cwd = env::current_dir()?;
-        env::set_current_dir(&path)?;
-
-        result = Command::new("dget").args(&["-uxqd", dsc]).run();
-        if let Err(why) = env::set_current_dir(cwd) {
-            panic!("failed to set directory to original location: {}", why);
-        }
+fn download_dsc(name: &str, dsc: &str, suite: &str) -> io::Result<()> {
+    let path = PathBuf::from(["build/", suite, "/", name].concat());
+    if path.join(crate::misc::filename_from_url(dsc)).exists() {
+        return Ok(());
     }
 
-    result
+    fs::create_dir_all(&path)?;
+    Command::new("dget")
+        .args(["-uxqd", dsc])
+        .current_dir(&path)
+        .run()
 }
@@ -1,26 +1,43 @@
-use crate::checksum::hasher;
+use super::cache::{DebCache, DebMetadata};
+use crate::checksum::Digests;
 use crate::config::Config;
+use crate::debian::arch::{self, ArchAll, Architectures};
 use crate::debian::{self, *};
+use crate::iter_reader::IteratorReader;
 use crate::misc;
+use crate::sign::SecretKeys;
+use chrono::Utc;
 use deb_version::compare_versions;
 use debarchive::Archive as DebArchive;
-use md5::Md5;
+use itertools::Itertools;
 use rayon::{self, prelude::*};
-use sha1::Sha1;
-use sha2::{Sha256, Sha512};
-use std::cmp::Ordering;
-use std::collections::hash_map::{Entry, HashMap};
+use std::collections::{BTreeSet, HashMap};
 use std::{
-    env,
     fs::{self, File},
-    io::{self, Write},
+    io::{self, BufWriter, Write},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::Command,
 };
+use walkdir::WalkDir;
 
 use crate::compress::*;
 
-pub(crate) fn sources_index(component: &str, dist_base: &str, pool_base: &str) -> io::Result<()> {
+/// The component beneath each component which debug symbols are indexed in.
+pub(crate) const DEBUG_COMPONENT: &str = "debug";
+
+/// The component beneath each component which debian-installer packages are indexed in.
+const INSTALLER_COMPONENT: &str = "debian-installer";
+
+/// The format of the `Date` and `Valid-Until` fields in the Release file.
+const RELEASE_DATE: &str = "%a, %d %b %Y %H:%M:%S UTC";
+
+/// Generates the `Sources` index from the `.dsc` files in the source pool of a component.
+pub(crate) fn sources_index(
+    options: &CompressOptions,
+    component: &str,
+    dist_base: &str,
+    pool_base: &str,
+) -> io::Result<()> {
     let pool_path = PathBuf::from(pool_base).join("source");
     if !pool_path.exists() {
         return Ok(());
@@ -30,96 +47,172 @@ pub(crate) fn sources_index(component: &str, dist_base: &str, pool_base: &str) -
     let path = PathBuf::from([dist_base, "/", component, "/source/"].concat());
     fs::create_dir_all(&path)?;
 
-    Command::new("apt-ftparchive")
-        .arg("sources")
-        .arg(pool_path)
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .spawn()
-        .and_then(|mut child| {
-            {
-                let stdout = child.stdout.as_mut().unwrap();
-                compress(
-                    "Sources",
-                    &path,
-                    stdout,
-                    UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS | ZSTD_COMPRESS,
-                )?;
-            }
+    let mut dscs = WalkDir::new(&pool_path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry.file_type().is_file()
+                && entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.ends_with(".dsc"))
+        })
+        .map(|entry| entry.into_path())
+        .collect::<Vec<PathBuf>>();
 
-            child.wait().and_then(|stat| {
-                if stat.success() {
-                    Ok(())
-                } else {
-                    Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "apt-ftparchive failed",
-                    ))
-                }
-            })
+    dscs.par_sort_unstable();
+
+    let sources = dscs
+        .par_iter()
+        .map(|dsc| SourceEntry::new(dsc).map(SourceEntry::generate_entry))
+        .collect::<io::Result<Vec<Vec<u8>>>>()?;
+
+    let sources_reader = IteratorReader::new(
+        Itertools::intersperse(sources.into_iter(), vec![b'\n']),
+        Vec::with_capacity(64 * 1024),
+    );
+
+    compress("Sources", &path, sources_reader, options)
+}
+
+/// A file within the dist directory, and its checksums, which the `Release` file will list.
+struct ReleaseEntry {
+    path: String,
+    size: u64,
+    md5sum: String,
+    sha1: String,
+    sha256: String,
+    sha512: String,
+}
+
+impl ReleaseEntry {
+    fn new(base: &Path, path: &Path) -> io::Result<Self> {
+        let size = fs::metadata(path)?.len();
+        let digests = Digests::from_path(path)?;
+
+        let path = path
+            .strip_prefix(base)
+            .ok()
+            .and_then(|path| path.to_str())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid path in dist directory: {}", path.display()),
+                )
+            })?
+            .to_owned();
+
+        Ok(ReleaseEntry {
+            path,
+            size,
+            md5sum: digests.md5sum,
+            sha1: digests.sha1,
+            sha256: digests.sha256,
+            sha512: digests.sha512,
         })
+    }
 }
 
-// TODO: this can easily be replaced with Rust.
-/// Generates the dists release file via `apt-ftparchive`.
-pub(crate) fn dists_release(config: &Config, base: &str, components: &[String]) -> io::Result<()> {
+type DigestField = fn(&ReleaseEntry) -> &str;
+
+/// Collects the checksums of every file in the dist directory, sorted by their path.
+fn release_entries(base: &Path) -> io::Result<Vec<ReleaseEntry>> {
+    let files = WalkDir::new(base)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "by-hash")
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            entry.depth() != 1
+                || !["Release", "InRelease", "Release.gpg"]
+                    .iter()
+                    .any(|name| entry.file_name() == *name)
+        })
+        .map(|entry| entry.into_path())
+        .collect::<Vec<PathBuf>>();
+
+    let mut entries = files
+        .par_iter()
+        .map(|path| ReleaseEntry::new(base, path))
+        .collect::<io::Result<Vec<ReleaseEntry>>>()?;
+
+    entries.par_sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Generates the dists release file, which lists the checksums of every file in the dist directory.
+pub(crate) fn dists_release(
+    config: &Config,
+    suite: &str,
+    base: &str,
+    components: &[String],
+) -> io::Result<()> {
     log::info!("generating dists release files");
 
-    let cwd = env::current_dir()?;
-    env::set_current_dir(base)?;
+    let base = Path::new(base);
+    let entries = release_entries(base)?;
+
+    let architectures = config.arches();
+    let architectures = architectures.with_all().collect::<Vec<_>>();
+
+    let mut release = BufWriter::new(File::create(base.join("Release"))?);
+    writeln!(&mut release, "Origin: {}", config.origin)?;
+    writeln!(&mut release, "Label: {}", config.label)?;
+    writeln!(&mut release, "Suite: {}", suite)?;
+    writeln!(&mut release, "Version: {}", config.version)?;
+    writeln!(&mut release, "Codename: {}", suite)?;
+    let date = Utc::now();
+    writeln!(&mut release, "Date: {}", date.format(RELEASE_DATE))?;
+    if let Some(valid_for) = config.release.valid_until {
+        let valid_until = date.checked_add_signed(valid_for).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "release.valid_until is too far in the future",
+            )
+        })?;
+
+        writeln!(
+            &mut release,
+            "Valid-Until: {}",
+            valid_until.format(RELEASE_DATE)
+        )?;
+    }
 
-    let components = components.iter().fold(String::new(), |mut acc, x| {
-        acc.push_str(&x);
-        acc.push(' ');
-        acc
-    });
+    write_release_fields(&mut release, config)?;
+    if config.arch_all == ArchAll::Merged {
+        writeln!(&mut release, "No-Support-for-Architecture-all: Packages")?;
+    }
 
-    let release = Command::new("apt-ftparchive")
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Origin={}",
-            config.origin
-        ))
-        .arg("-o")
-        .arg(format!("APT::FTPArchive::Release::Label={}", config.label))
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Suite={}",
-            config.archive
-        ))
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Version={}",
-            config.version
-        ))
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Codename={}",
-            config.archive
-        ))
-        .arg("-o")
-        .arg("APT::FTPArchive::Release::Architectures=i386 amd64 all")
-        .arg("-o")
-        .arg(
-            [
-                "APT::FTPArchive::Release::Components=",
-                components.trim_end(),
-            ]
-            .concat(),
-        )
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Description={} ({} {})",
-            config.label, config.archive, config.version
-        ))
-        .arg("release")
-        .arg(".")
-        .output()
-        .map(|data| data.stdout)?;
-
-    let mut release_file = File::create("Release")?;
-    release_file.write_all(&release)?;
-    env::set_current_dir(cwd)
+    writeln!(&mut release, "Architectures: {}", architectures.join(" "))?;
+    writeln!(&mut release, "Components: {}", components.join(" "))?;
+    writeln!(
+        &mut release,
+        "Description: {} ({} {})",
+        config.label, suite, config.version
+    )?;
+
+    let sections: [(&str, DigestField); 4] = [
+        ("MD5Sum", |entry| &entry.md5sum),
+        ("SHA1", |entry| &entry.sha1),
+        ("SHA256", |entry| &entry.sha256),
+        ("SHA512", |entry| &entry.sha512),
+    ];
+
+    for (section, digest) in &sections {
+        writeln!(&mut release, "{}:", section)?;
+        for entry in &entries {
+            writeln!(
+                &mut release,
+                " {} {:>16} {}",
+                digest(entry),
+                entry.size,
+                entry.path
+            )?;
+        }
+    }
+
+    release.flush()
 }
 
 /// Generates the `InRelease` file from the `Release` file via `gpg --clearsign`.
@@ -175,175 +268,259 @@ pub(crate) fn gpg_release(email: &str, release_path: &Path, out_path: &Path) ->
     }
 }
 
-fn binary_suites(pool_base: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+/// Generates the `InRelease` file from the `Release` file, with a signature from each key.
+pub(crate) fn native_in_release(
+    keys: &SecretKeys,
+    release_path: &Path,
+    out_path: &Path,
+) -> io::Result<()> {
+    log::info!("generating InRelease file");
+    let release = misc::read_to_string(release_path)?;
+    fs::write(out_path, keys.clearsign(&release)?)
+}
+
+/// Generates the `Release.gpg` file from the `Release` file, with a signature from each key.
+pub(crate) fn native_release(
+    keys: &SecretKeys,
+    release_path: &Path,
+    out_path: &Path,
+) -> io::Result<()> {
+    log::info!("generating Release.gpg file");
+    let release = misc::read(release_path)?;
+    fs::write(out_path, keys.detach_sign(&release)?)
+}
+
+/// Collects the binary directories in the pool of a component, for each configured architecture.
+fn binary_suites(
+    pool_base: &Path,
+    architectures: &Architectures,
+) -> io::Result<Vec<(String, PathBuf)>> {
     Ok(fs::read_dir(pool_base)?
         .filter_map(|entry| {
             let entry = entry.ok()?;
-            let arch = entry.file_name();
-            if &arch == "source" {
-                None
+            let name = entry.file_name();
+            let arch = arch::from_binary_dir(name.to_str()?)?;
+            if architectures.contains(arch) {
+                Some((arch.to_owned(), entry.path()))
             } else {
-                let path = pool_base.join(&arch);
-                let arch = match arch.to_str().unwrap() {
-                    "binary-amd64" => "amd64",
-                    "binary-arm64" => "arm64",
-                    "binary-armel" => "armel",
-                    "binary-armhf" => "armhf",
-                    "binary-i386" => "i386",
-                    "binary-mips" => "mips",
-                    "binary-mipsel" => "mipsel",
-                    "binary-mips64el" => "mips64el",
-                    "binary-ppc64el" => "ppc64el",
-                    "binary-s390x" => "s390x",
-                    "binary-all" => "all",
-                    arch => panic!("unsupported architecture: {}", arch),
-                };
-
-                Some((arch.to_owned(), path))
+                log::warn!(
+                    "skipping {}, as {} is not a configured architecture",
+                    entry.path().display(),
+                    arch
+                );
+                None
             }
         })
         .collect())
 }
 
+/// Reads the control file, the list of files, and the digests of a deb archive.
+fn read_deb(path: &Path) -> io::Result<DebMetadata> {
+    log::info!("processing contents of {:?}", path);
+
+    // Open the Debian archive, and get the IDs & required codecs for the inner control and data archives.
+    let archive = DebArchive::new(path)?;
+    // Open the control file within the control archive and read each of its fields.
+    let control = debian::archive::control_fields(&archive)?;
+
+    // Now get a listing of all the files for the Contents archive.
+    let mut files: Vec<PathBuf> = Vec::new();
+    // And the files which describe the AppStream components of the package.
+    let mut appstream = AppStreamFiles::default();
+
+    // The files are listed while the digests are computed.
+    let (contents, digests) = rayon::join(
+        || {
+            archive.data(|entry| {
+                let path = entry.path()?.to_path_buf();
+                if entry.header().entry_type().is_file() {
+                    appstream.collect(&path, entry)?;
+                }
+
+                files.push(path);
+                Ok(())
+            })
+        },
+        || Digests::from_path(path),
+    );
+
+    contents?;
+    Ok(DebMetadata {
+        control,
+        files,
+        size: fs::metadata(path)?.len(),
+        digests: digests?,
+        appstream,
+    })
+}
+
+/// Adds the packages and contents of the `all` architecture to every other architecture.
+fn merge_arch_all(entries: &mut debian::Entries, architectures: &Architectures) {
+    let (all_packages, all_contents) = match entries.get(arch::ALL) {
+        Some((packages, contents)) => (packages.clone(), contents.clone()),
+        None => return,
+    };
+
+    for arch in architectures.iter() {
+        let (packages, contents) = entries.entry(arch.to_owned()).or_default();
+        for (component, all_packages) in &all_packages {
+            packages
+                .entry(component.clone())
+                .or_default()
+                .extend(all_packages.iter().cloned());
+        }
+
+        contents.extend(all_contents.iter().cloned());
+    }
+}
+
 type ProcessedResults =
     Vec<io::Result<(PackageEntry, ContentsEntry, debian::Arch, debian::Component)>>;
 
+/// How the dist files of a suite are generated.
+pub(crate) struct DistOptions<'a> {
+    pub compress: CompressOptions,
+    /// Where the translated descriptions of each package are found, if they are enabled.
+    pub translations: Option<&'a Path>,
+    /// The metadata of the debs which have been read before.
+    pub cache: &'a DebCache,
+    /// The staging directory of the debug suite, if debug symbols are a separate suite.
+    pub debug_base: Option<&'a str>,
+}
+
+/// Generates the Packages, Contents, and Release files of each component and architecture.
+///
+/// Debug symbols are indexed in the `debug` component beneath each component, unless a
+/// `debug_base` is given, where they will be indexed as a separate suite instead. The
+/// components which debug symbols were indexed for are returned.
 pub(crate) fn dists(
     config: &Config,
     dist_base: &str,
     pool_base: &Path,
     components: &[String],
-) -> io::Result<()> {
+    options: &DistOptions,
+) -> io::Result<BTreeSet<String>> {
     log::info!("generating dist archives");
 
+    let DistOptions {
+        compress: ref options,
+        translations,
+        cache,
+        debug_base,
+    } = *options;
+
     let origin = &config.origin;
+    let architectures = config.arches();
 
     // Collect the entries for each architecture of each component.
     let entries = components
         .par_iter()
         .map(|component| {
             // Collect the entries for each architecture of this component
-            binary_suites(&pool_base.join(&component))
+            binary_suites(&pool_base.join(component), &architectures)
                 .unwrap()
                 .into_par_iter()
                 .map(|(arch, path)| {
-                    // Collect a list of packages to process for this architecture.
-                    // This list will have older entries filtered.
-                    let mut archives: HashMap<String, (String, PathBuf)> = HashMap::new();
-
-                    // An iterator that returns debian archives found in the path.
-                    let deb_iter = misc::walk_debs(&path, true)
+                    // Every deb is read, or found in the cache, so that the versions of each
+                    // package may be grouped with the source package they were built from.
+                    let debs = misc::walk_debs(&path, misc::INCLUDE_DDEB | misc::INCLUDE_UDEB)
                         .filter(|e| !e.file_type().is_dir())
-                        .map(|e| e.path().to_path_buf());
-
-                    for package in deb_iter {
-                        if let Some((name, version)) = get_debian_package_info(&package) {
-                            match archives.entry(name) {
-                                Entry::Occupied(mut entry) => {
-                                    if compare_versions(&entry.get().0, &version) == Ordering::Less
-                                    {
-                                        eprintln!("replacing {} with {}", entry.get().0, &version);
-                                        entry.insert((version, package));
-                                    }
-                                }
-                                Entry::Vacant(entry) => {
-                                    entry.insert((version, package));
-                                }
-                            }
-                        }
+                        .map(|e| e.path().to_path_buf())
+                        .collect::<Vec<PathBuf>>()
+                        .into_par_iter()
+                        .filter_map(|package| {
+                            let (name, version) = get_debian_package_info(&package)?;
+                            Some(
+                                cache
+                                    .metadata(&package, read_deb)
+                                    .map(|metadata| (name, version, package, metadata)),
+                            )
+                        })
+                        .collect::<io::Result<Vec<_>>>();
+
+                    let debs = match debs {
+                        Ok(debs) => debs,
+                        Err(why) => return vec![Err(why)],
+                    };
+
+                    let mut archives: HashMap<String, Vec<_>> = HashMap::new();
+                    for (name, version, package, metadata) in debs {
+                        archives
+                            .entry(name)
+                            .or_default()
+                            .push((version, package, metadata));
                     }
 
+                    // Only the newest versions of each package will be indexed.
+                    let archives = archives
+                        .into_iter()
+                        .flat_map(|(name, mut versions)| {
+                            versions.sort_by(|a, b| compare_versions(&b.0, &a.0));
+                            let package = name
+                                .strip_suffix("_d")
+                                .or_else(|| name.strip_suffix("_u"))
+                                .unwrap_or(&name);
+                            let source = control::get(&versions[0].2.control, "Source");
+                            let keep = config.index_versions(package, source);
+                            for (version, _, _) in versions.iter().skip(keep) {
+                                log::debug!("not indexing {} {}", name, version);
+                            }
+
+                            versions.truncate(keep);
+                            versions
+                        })
+                        .collect::<Vec<_>>();
+
                     // Collect the entries for this architecture of this component
                     archives
                         .into_par_iter()
-                        .map(|(_, (_, debian_entry))| {
-                            log::info!("processing contents of {:?}", debian_entry);
-
+                        .map(|(_, debian_entry, metadata)| {
                             let arch: &str = &arch;
                             let component: &str = &component;
 
-                            // Open the Debian archive, and get the IDs & required codecs for the inner control and data archives.
-                            let archive = DebArchive::new(&debian_entry)?;
-                            // Open the control file within the control archive and read each key / value pair into a map.
-                            let control = archive.control_map()?;
+                            let DebMetadata {
+                                control,
+                                files,
+                                size,
+                                digests,
+                                appstream,
+                            } = metadata;
+
+                            let relations = Relations::new(&control);
 
                             // The Contents archive requires that we know the package and section keys for each Debian package beforehand.
-                            let package_name =
-                                match (control.get("Package"), control.get("Section")) {
-                                    (Some(ref package), Some(ref section))
-                                        if component == "main" =>
-                                    {
-                                        [section, "/", package].concat()
-                                    }
-                                    (Some(ref package), Some(ref section)) => {
-                                        [component, "/", section, "/", package].concat()
-                                    }
-                                    _ => {
-                                        return Err(io::Error::new(
-                                            io::ErrorKind::Other,
-                                            "did not find package + section from control archive",
-                                        ));
-                                    }
-                                };
-
-                            // Now get a listing of all the files for the Contents archive.
-                            let mut files: Vec<PathBuf> = Vec::new();
-
-                            // Runs each scope in parallel to generate the contents and checksums.
-                            let (content_res, ((sha1_res, sha256_res), (sha512_res, md5_res))) = {
-                                let path = &debian_entry;
-                                // TODO: use bus_writer instead of reading the same file in each thread.
-                                let generate_hashes = || {
-                                    rayon::join(
-                                        || {
-                                            rayon::join(
-                                                || File::open(path).and_then(hasher::<Sha1, File>),
-                                                || {
-                                                    File::open(path)
-                                                        .and_then(hasher::<Sha256, File>)
-                                                },
-                                            )
-                                        },
-                                        || {
-                                            rayon::join(
-                                                || {
-                                                    File::open(path)
-                                                        .and_then(hasher::<Sha512, File>)
-                                                },
-                                                || File::open(path).and_then(hasher::<Md5, File>),
-                                            )
-                                        },
-                                    )
-                                };
-
-                                rayon::join(
-                                    || {
-                                        archive.data(|entry| {
-                                            let path = entry.path()?;
-                                            files.push(path.to_path_buf());
-                                            Ok(())
-                                        })
-                                    },
-                                    generate_hashes,
-                                )
+                            let package_name = match (
+                                control::get(&control, "Package"),
+                                control::get(&control, "Section"),
+                            ) {
+                                (Some(package), Some(section)) if component == "main" => {
+                                    [section, "/", package].concat()
+                                }
+                                (Some(package), Some(section)) => {
+                                    [component, "/", section, "/", package].concat()
+                                }
+                                _ => {
+                                    return Err(io::Error::other(
+                                        "did not find package + section from control archive",
+                                    ));
+                                }
                             };
 
-                            drop(archive);
-                            content_res?;
                             let package_entry = PackageEntry {
                                 control,
-                                filename: debian_entry.clone(),
-                                size: File::open(&debian_entry)
-                                    .and_then(|file| file.metadata().map(|m| m.len()))?,
-                                md5sum: md5_res?,
-                                sha1: sha1_res?,
-                                sha256: sha256_res?,
-                                sha512: sha512_res?,
+                                filename: debian_entry,
+                                size,
+                                md5sum: digests.md5sum,
+                                sha1: digests.sha1,
+                                sha256: digests.sha256,
+                                sha512: digests.sha512,
+                                appstream,
                             };
 
                             let contents_entry = ContentsEntry {
                                 package: package_name,
                                 files,
+                                relations,
                             };
                             let arch: String = arch.to_owned();
                             let component: String = component.to_owned();
@@ -364,34 +541,259 @@ pub(crate) fn dists(
     // Validate the results of each parallel process, and collect them in a manner so that they
     // may be used for further parallel processing and compression.
     let mut entries_map: debian::Entries = HashMap::new();
+    let mut debug_map: debian::Entries = HashMap::new();
+    let mut debug_components = BTreeSet::new();
+    let mut installer_contents: HashMap<(debian::Component, debian::Arch), Vec<ContentsEntry>> =
+        HashMap::new();
+
     for result in entries {
         let (package, contents, arch, component) = result?;
+        let filename = package.filename.to_str().unwrap_or_default();
+
+        // Debug symbols are indexed in a debug component, or in the debug suite, and they are
+        // excluded from the Contents indices.
+        if filename.ends_with(debian::DEB_DEBUG_EXTENSION) {
+            debug_components.insert(component.clone());
+            let (map, component) = match debug_base {
+                None => (
+                    &mut entries_map,
+                    [&component, "/", DEBUG_COMPONENT].concat(),
+                ),
+                Some(_) => (&mut debug_map, component),
+            };
+
+            let (packages, _) = map.entry(arch).or_default();
+            packages.entry(component).or_default().push(package);
+            continue;
+        }
 
-        match entries_map.entry(arch) {
-            Entry::Occupied(mut entry) => {
-                let entry = entry.get_mut();
-                match (*entry).0.entry(component) {
-                    Entry::Occupied(mut entry) => {
-                        (*entry.get_mut()).push(package);
-                    }
-                    Entry::Vacant(entry) => {
-                        entry.insert(vec![package]);
-                    }
-                }
-                (*entry).1.push(contents);
-            }
-            Entry::Vacant(entry) => {
-                entry.insert({
-                    let mut component_map = HashMap::new();
-                    component_map.insert(component, vec![package]);
-                    (component_map, vec![contents])
-                });
+        // Packages of the debian-installer are indexed in their own component, and have their
+        // own Contents indices within the component.
+        if filename.ends_with(debian::DEB_INSTALLER_EXTENSION) {
+            let installer = [&component, "/", INSTALLER_COMPONENT].concat();
+            let (packages, _) = entries_map.entry(arch.clone()).or_default();
+            packages.entry(installer).or_default().push(package);
+            installer_contents
+                .entry((component, arch))
+                .or_default()
+                .push(contents);
+            continue;
+        }
+
+        let (packages, contents_entries) = entries_map.entry(arch).or_default();
+        packages.entry(component).or_default().push(package);
+        contents_entries.push(contents);
+    }
+
+    if config.arch_all == ArchAll::Merged {
+        merge_arch_all(&mut entries_map, &architectures);
+        merge_arch_all(&mut debug_map, &architectures);
+
+        let all_contents = installer_contents
+            .iter()
+            .filter(|((_, arch), _)| arch == arch::ALL)
+            .map(|((component, _), contents)| (component.clone(), contents.clone()))
+            .collect::<Vec<_>>();
+
+        for (component, contents) in all_contents {
+            for arch in architectures.iter() {
+                installer_contents
+                    .entry((component.clone(), arch.to_owned()))
+                    .or_default()
+                    .extend(contents.iter().cloned());
             }
         }
     }
 
+    installer_contents
+        .into_par_iter()
+        .map(|((component, arch), contents)| {
+            let name = ["Contents-udeb-", &arch].concat();
+            write_contents(
+                &name,
+                &Path::new(dist_base).join(component),
+                contents,
+                options,
+            )
+        })
+        .collect::<io::Result<()>>()?;
+
     let destination = &Path::new(dist_base);
     let dist_files = DistFiles::new(destination, entries_map);
-    // Re-enable duplicates checking.
-    dist_files.compress_and_release(config, origin, None)
+    dist_files.check_for_duplicates(config)?;
+    dist_files.compress_and_release(config, options, origin, None, translations)?;
+
+    if let Some(debug_base) = debug_base
+        && !debug_map.is_empty()
+    {
+        DistFiles::new(Path::new(debug_base), debug_map).compress_and_release(
+            config,
+            options,
+            origin,
+            None,
+            translations,
+        )?;
+    }
+
+    Ok(debug_components)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clients request each index by the SHA256 or SHA512 digest that the Release file lists.
+    #[test]
+    fn release_digests_are_stored_by_hash() {
+        let config: Config = toml::from_str(
+            r#"
+            archive = "stable"
+            version = "1.0"
+            origin = "Test"
+            label = "Test"
+            email = "test@example.com"
+            "#,
+        )
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        let binary = base.join("main/binary-amd64");
+        fs::create_dir_all(&binary).unwrap();
+
+        let options = CompressOptions {
+            support: UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS | ZSTD_COMPRESS,
+            zstd_level: 3,
+            by_hash: Some(1),
+        };
+
+        compress("Packages", &binary, &b"Package: foo\n"[..], &options).unwrap();
+        compress(
+            "Contents-amd64",
+            &base.join("main"),
+            &b"usr/bin/foo  foo\n"[..],
+            &options,
+        )
+        .unwrap();
+
+        dists_release(&config, "stable", base.to_str().unwrap(), &["main".into()]).unwrap();
+
+        let release = fs::read_to_string(base.join("Release")).unwrap();
+        let mut section = None;
+        let mut checked = 0;
+        for line in release.lines() {
+            if !line.starts_with(' ') {
+                section = match line {
+                    "SHA256:" => Some(BY_HASH[0]),
+                    "SHA512:" => Some(BY_HASH[1]),
+                    _ => None,
+                };
+                continue;
+            }
+
+            if let Some(directory) = section {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let (digest, path) = (fields[0], Path::new(fields[2]));
+                let stored = base
+                    .join(path.parent().unwrap())
+                    .join(directory)
+                    .join(digest);
+                assert!(stored.is_file(), "{} is missing", stored.display());
+                checked += 1;
+            }
+        }
+
+        assert_eq!(checked, 16);
+    }
+
+    /// The staged suites contain the by-hash indices of the live suites, so the debug components
+    /// must be found by the indices which were generated for them.
+    #[test]
+    fn debug_components_without_debug_symbols() {
+        let config: Config = toml::from_str(
+            r#"
+            archive = "stable"
+            version = "1.0"
+            origin = "Test"
+            label = "Test"
+            email = "test@example.com"
+            "#,
+        )
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let (live, staging) = (dir.path().join("live"), dir.path().join("staging"));
+        let (debug_live, debug_staging) = (
+            dir.path().join("live-debug"),
+            dir.path().join("staging-debug"),
+        );
+        for binary in [
+            live.join("main/debug/binary-amd64"),
+            debug_live.join("main/binary-amd64"),
+        ] {
+            let by_hash = binary.join(BY_HASH[0]);
+            fs::create_dir_all(&by_hash).unwrap();
+            fs::write(by_hash.join("0123"), "Package: hello-dbgsym\n").unwrap();
+        }
+
+        let pool = dir.path().join("pool");
+        let package = pool.join("main/binary-amd64/h/hello");
+        fs::create_dir_all(&package).unwrap();
+        let build = |filename: &str, name: &str, section: &str| {
+            let fields = control::parse(&format!(
+                "Package: {name}\nVersion: 1.0\nArchitecture: amd64\n\
+                 Maintainer: Test <test@example.com>\nPriority: optional\n\
+                 Section: {section}\nDescription: {name}\n"
+            ));
+            archive::build(
+                &package.join(filename),
+                &fields,
+                archive::DebCompression::Xz,
+            )
+            .unwrap();
+        };
+
+        build("hello_1.0_amd64.deb", "hello", "utils");
+
+        let cache = DebCache::load(dir.path().join("cache.json"));
+        let generate = |debug_base: Option<&Path>| {
+            for (live, staging) in [(&live, &staging), (&debug_live, &debug_staging)] {
+                super::super::publish::prepare(live, staging, true).unwrap();
+            }
+
+            let options = DistOptions {
+                compress: CompressOptions {
+                    support: UNCOMPRESSED,
+                    zstd_level: 3,
+                    by_hash: Some(1),
+                },
+                translations: None,
+                cache: &cache,
+                debug_base: debug_base.and_then(Path::to_str),
+            };
+
+            dists(
+                &config,
+                staging.to_str().unwrap(),
+                &pool,
+                &["main".into()],
+                &options,
+            )
+            .unwrap()
+        };
+
+        for debug_base in [None, Some(debug_staging.as_path())] {
+            assert!(generate(debug_base).is_empty());
+        }
+
+        assert!(staging.join("main/debug").is_dir());
+        assert!(debug_staging.join("main").is_dir());
+
+        build("hello-dbgsym_1.0_amd64.ddeb", "hello-dbgsym", "debug");
+        let main = BTreeSet::from(["main".to_owned()]);
+        assert_eq!(generate(None), main);
+        assert!(staging.join("main/debug/binary-amd64/Packages").is_file());
+        assert_eq!(generate(Some(&debug_staging)), main);
+        assert!(debug_staging.join("main/binary-amd64/Packages").is_file());
+    }
 }
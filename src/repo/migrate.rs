@@ -1,6 +1,6 @@
 use super::{ReleaseError, generate_release_files};
 use crate::config::Config;
-use crate::misc::{INCLUDE_DDEB, INCLUDE_SRCS, is_deb};
+use crate::misc::{INCLUDE_DDEB, INCLUDE_SRCS, INCLUDE_UDEB, is_deb};
 use std::fs;
 use std::io::{self, Error};
 use std::os::unix::fs::MetadataExt;
@@ -62,7 +62,7 @@ fn inner_migrate(
                 .filter_entry(|e| match e.depth() {
                     1 | 2 => true,
                     3 => &e.file_name() == package,
-                    4 => is_deb(e, INCLUDE_DDEB | INCLUDE_SRCS),
+                    4 => is_deb(e, INCLUDE_DDEB | INCLUDE_SRCS | INCLUDE_UDEB),
                     _ => false,
                 })
                 .flat_map(|e| e.ok())
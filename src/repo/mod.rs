@@ -1,21 +1,38 @@
 mod build;
+mod cache;
 mod download;
 mod generate;
 mod migrate;
+mod outdated;
 mod pool;
 mod prepare;
+mod publish;
+mod update;
 mod version;
 
 pub use self::migrate::migrate;
-
-use crate::config::Config;
+pub use self::outdated::outdated;
+pub use self::update::update;
+
+use self::cache::DebCache;
+use self::generate::DistOptions;
+use self::publish::{PREVIOUS_DISTS, STAGING_DISTS};
+use crate::compress::prune_by_hash;
+use crate::config::{Config, SigningBackend};
+use crate::debian::DebugSymbols;
+use crate::debian::arch::{self, Architectures};
 use crate::misc::remove_empty_directories_from;
+use crate::sign::SecretKeys;
+use chrono::Utc;
 use rayon;
 use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::{env, fs, io};
 
+/// Where the metadata of the debs in the pool of each suite is cached between runs.
+const DEB_CACHE: &str = "assets/cache/dists/";
+
 pub enum Packages<'a> {
     All,
     Select(&'a [&'a str], bool),
@@ -93,40 +110,81 @@ impl<'a> Repo<'a> {
 
 #[derive(Debug, thiserror::Error)]
 pub enum ReleaseError {
+    #[error("failed to prune by-hash indices in {:?}: {}", path, why)]
+    ByHashPrune { path: PathBuf, why: io::Error },
     #[error("failed to collect component names from {:?}", pool)]
     Components { pool: PathBuf, why: io::Error },
     #[error("failed to generate distribution files for {}: {}", suite, why)]
     DistGeneration { suite: String, why: io::Error },
     #[error("failed to generate dist release files for {}: {}", archive, why)]
     DistRelease { archive: String, why: io::Error },
-    #[error("failed to remove dists directory at {:?}: {}", path, why)]
-    DistRemoval { path: PathBuf, why: io::Error },
     #[error("failed to generate InRelease file: {}", why)]
     InRelease { why: io::Error },
     #[error("pool cleanup failure at {:?}: {}", path, why)]
     PoolCleanup { path: PathBuf, why: io::Error },
+    #[error("failed to publish dist files to {:?}: {}", path, why)]
+    Publish { path: PathBuf, why: io::Error },
     #[error("failed to generate Release.gpg file: {}", why)]
     ReleaseGPG { why: io::Error },
+    #[error("failed to roll back dist files at {:?}: {}", path, why)]
+    Rollback { path: PathBuf, why: io::Error },
+    #[error("failed to load signing keys: {}", why)]
+    SigningKeys { why: io::Error },
     #[error("failed to generate source index: {}", why)]
     Source { why: io::Error },
+    #[error("failed to prepare staging directory at {:?}: {}", path, why)]
+    Staging { path: PathBuf, why: io::Error },
 }
 
 /// Generate the dist release files from the existing binary and source files.
 pub fn generate_release_files(sources: &Config) -> Result<(), ReleaseError> {
+    // Key paths are relative to the suite's config, so they must be read before switching dirs.
+    let keys = match sources.signing.backend {
+        SigningBackend::Gpg => None,
+        SigningBackend::Native => Some(
+            SecretKeys::load(&sources.signing.keys)
+                .map_err(|why| ReleaseError::SigningKeys { why })?,
+        ),
+    };
+
+    let cwd = env::current_dir().expect("unable to get current dir");
+
+    // Translated descriptions are stored alongside the debian files of each package.
+    let translations = if sources.translations {
+        Some(cwd.join(["debian/", &sources.archive].concat()))
+    } else {
+        None
+    };
+
+    // The metadata of each deb in the pool is kept between runs.
+    let cache = DebCache::load(cwd.join([DEB_CACHE, &sources.archive, ".json"].concat()));
+
     env::set_current_dir("repo").expect("unable to switch dir to repo");
 
-    let base = ["dists/", &sources.archive].concat();
     let pool = ["pool/", &sources.archive, "/"].concat();
     let pool_path = &Path::new(&pool);
 
-    {
-        let base = &Path::new(&base);
-        if base.exists() {
-            fs::remove_dir_all(&base).map_err(|why| ReleaseError::DistRemoval {
-                path: base.to_path_buf(),
+    // Debug symbols may be published as a separate suite, beside the suite itself.
+    let debug_suite = match sources.debug_symbols {
+        DebugSymbols::Component => None,
+        DebugSymbols::Suite => Some([&sources.archive, "-", generate::DEBUG_COMPONENT].concat()),
+    };
+
+    let suites = Some(&sources.archive)
+        .into_iter()
+        .chain(debug_suite.as_ref())
+        .map(|suite| StagedSuite::new(suite))
+        .collect::<Vec<_>>();
+
+    // The dist files are generated in a staging directory, and will only replace the live
+    // dist files after they have been generated and signed.
+    for suite in &suites {
+        publish::prepare(&suite.live, Path::new(&suite.base), sources.by_hash.enabled).map_err(
+            |why| ReleaseError::Staging {
+                path: PathBuf::from(&suite.base),
                 why,
-            })?;
-        }
+            },
+        )?;
     }
 
     remove_empty_directories_from(pool_path).map_err(|why| ReleaseError::PoolCleanup {
@@ -134,22 +192,40 @@ pub fn generate_release_files(sources: &Config) -> Result<(), ReleaseError> {
         why,
     })?;
 
-    let release = PathBuf::from([&base, "/Release"].concat());
-    let in_release = PathBuf::from([&base, "/InRelease"].concat());
-    let release_gpg = PathBuf::from([&base, "/Release.gpg"].concat());
-
-    let components =
-        collect_components(pool_path, &base).map_err(|why| ReleaseError::Components {
+    let base = &suites[0].base;
+    let components = collect_components(pool_path, base, &sources.arches()).map_err(|why| {
+        ReleaseError::Components {
             pool: pool_path.to_path_buf(),
             why,
-        })?;
+        }
+    })?;
+
+    let options = sources.compress_options(Utc::now().timestamp());
 
     // Generates the dist directory's archives in parallel.
-    generate::dists(sources, &base, pool_path, &components).map_err(|why| {
-        ReleaseError::DistGeneration {
-            suite: sources.archive.clone(),
-            why,
-        }
+    let generated = generate::dists(
+        sources,
+        base,
+        pool_path,
+        &components,
+        &DistOptions {
+            compress: options,
+            translations: translations.as_deref(),
+            cache: &cache,
+            debug_base: suites.get(1).map(|suite| suite.base.as_str()),
+        },
+    );
+
+    // Debs which were read before a failure will not need to be read again.
+    if let Err(why) = cache.save() {
+        log::warn!("failed to save the deb cache: {}", why);
+    }
+
+    // Components are listed by the indices which were generated for them, rather than by the
+    // directories of the staged suite, which may only contain by-hash indices of the live suite.
+    let debug_components = generated.map_err(|why| ReleaseError::DistGeneration {
+        suite: sources.archive.clone(),
+        why,
     })?;
 
     // TODO: Merge this functionality with generate::dists
@@ -158,33 +234,182 @@ pub fn generate_release_files(sources: &Config) -> Result<(), ReleaseError> {
         .par_iter()
         .map(|component| {
             let pool = [&pool, component.as_str()].concat();
-            generate::sources_index(&component, &base, &pool)
+            generate::sources_index(&options, component, base, &pool)
                 .map_err(|why| ReleaseError::Source { why })
         })
         .collect::<Result<(), ReleaseError>>()?;
 
-    generate::dists_release(sources, &base, &components).map_err(|why| {
+    let mut released = Vec::with_capacity(suites.len());
+    for suite in &suites {
+        let components = if suite.name != sources.archive {
+            debug_components.iter().cloned().collect()
+        } else if debug_suite.is_some() {
+            components.clone()
+        } else {
+            // Components which have debug symbols also list their debug component.
+            components
+                .iter()
+                .flat_map(|component| {
+                    let debug = debug_components
+                        .contains(component)
+                        .then(|| [component, "/", generate::DEBUG_COMPONENT].concat());
+                    Some(component.clone()).into_iter().chain(debug)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // The debug suite is not published when there are no debug symbols.
+        if components.is_empty() {
+            log::info!("{} has no components -- skipping", suite.name);
+            fs::remove_dir_all(&suite.base).map_err(|why| ReleaseError::Staging {
+                path: PathBuf::from(&suite.base),
+                why,
+            })?;
+            continue;
+        }
+
+        release_suite(sources, keys.as_ref(), suite, &components)?;
+        released.push(suite);
+    }
+
+    // If a suite fails to be published, the suites before it are rolled back, so that the
+    // suites which are live always match each other.
+    let mut published: Vec<(&StagedSuite, bool)> = Vec::with_capacity(released.len());
+    for suite in released.iter().copied() {
+        let replaced = suite.live.exists();
+        if let Err(why) = publish::publish(Path::new(&suite.base), &suite.live, &suite.previous) {
+            for &(suite, replaced) in published.iter().rev() {
+                log::warn!("rolling back {}", suite.live.display());
+                let result = if replaced {
+                    publish::rollback(&suite.live, &suite.previous)
+                } else {
+                    fs::rename(&suite.live, &suite.base)
+                };
+
+                if let Err(why) = result {
+                    log::error!("failed to roll back {}: {}", suite.live.display(), why);
+                }
+            }
+
+            return Err(ReleaseError::Publish {
+                path: suite.live.clone(),
+                why,
+            });
+        }
+
+        published.push((suite, replaced));
+    }
+
+    // A debug suite which no longer has any debug symbols is retired, where it may be restored
+    // from by rolling back.
+    for suite in suites.iter().skip(1) {
+        if !released.iter().any(|released| released.name == suite.name) && suite.live.exists() {
+            publish::retire(&suite.live, &suite.previous).map_err(|why| ReleaseError::Publish {
+                path: suite.live.clone(),
+                why,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The locations of the dist files of a suite, while they are being generated and published.
+struct StagedSuite {
+    name: String,
+    live: PathBuf,
+    previous: PathBuf,
+    base: String,
+}
+
+impl StagedSuite {
+    fn new(name: &str) -> Self {
+        StagedSuite {
+            name: name.to_owned(),
+            live: PathBuf::from(["dists/", name].concat()),
+            previous: PathBuf::from([PREVIOUS_DISTS, name].concat()),
+            base: [STAGING_DISTS, name].concat(),
+        }
+    }
+}
+
+/// Writes and signs the Release file of a staged suite, and prunes its by-hash indices.
+fn release_suite(
+    sources: &Config,
+    keys: Option<&SecretKeys>,
+    suite: &StagedSuite,
+    components: &[String],
+) -> Result<(), ReleaseError> {
+    let base = &suite.base;
+    let release = PathBuf::from([base, "/Release"].concat());
+    let in_release = PathBuf::from([base, "/InRelease"].concat());
+    let release_gpg = PathBuf::from([base, "/Release.gpg"].concat());
+
+    generate::dists_release(sources, &suite.name, base, components).map_err(|why| {
         ReleaseError::DistRelease {
-            archive: sources.archive.clone(),
+            archive: suite.name.clone(),
             why,
         }
     })?;
 
     let (inrelease, release) = rayon::join(
         || {
-            generate::gpg_in_release(&sources.email, &release, &in_release)
-                .map_err(|why| ReleaseError::InRelease { why })
+            match keys {
+                Some(keys) => generate::native_in_release(keys, &release, &in_release),
+                None => generate::gpg_in_release(&sources.email, &release, &in_release),
+            }
+            .map_err(|why| ReleaseError::InRelease { why })
         },
         || {
-            generate::gpg_release(&sources.email, &release, &release_gpg)
-                .map_err(|why| ReleaseError::ReleaseGPG { why })
+            match keys {
+                Some(keys) => generate::native_release(keys, &release, &release_gpg),
+                None => generate::gpg_release(&sources.email, &release, &release_gpg),
+            }
+            .map_err(|why| ReleaseError::ReleaseGPG { why })
         },
     );
 
-    inrelease.and(release)
+    inrelease.and(release)?;
+
+    if sources.by_hash.enabled {
+        prune_by_hash(Path::new(base), sources.by_hash.keep).map_err(|why| {
+            ReleaseError::ByHashPrune {
+                path: PathBuf::from(base),
+                why,
+            }
+        })?;
+    }
+
+    Ok(())
 }
 
-fn collect_components(pool: &Path, base: &str) -> io::Result<Vec<String>> {
+/// Restores the dist files which were published before the current dist files.
+pub fn rollback_release_files(config: &Config) -> Result<(), ReleaseError> {
+    let live = PathBuf::from(["repo/dists/", &config.archive].concat());
+    let previous = PathBuf::from(["repo/", PREVIOUS_DISTS, &config.archive].concat());
+
+    publish::rollback(&live, &previous)
+        .map_err(|why| ReleaseError::Rollback { path: live, why })?;
+
+    // The debug suite is only published when there are debug symbols.
+    if config.debug_symbols == DebugSymbols::Suite {
+        let suite = [&config.archive, "-", generate::DEBUG_COMPONENT].concat();
+        let live = PathBuf::from(["repo/dists/", &suite].concat());
+        let previous = PathBuf::from(["repo/", PREVIOUS_DISTS, &suite].concat());
+        if previous.exists() {
+            publish::rollback(&live, &previous)
+                .map_err(|why| ReleaseError::Rollback { path: live, why })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn collect_components(
+    pool: &Path,
+    base: &str,
+    architectures: &Architectures,
+) -> io::Result<Vec<String>> {
     let mut components = Vec::new();
 
     for component in pool.read_dir()? {
@@ -192,21 +417,13 @@ fn collect_components(pool: &Path, base: &str) -> io::Result<Vec<String>> {
             if component.path().is_dir() {
                 let component = component.file_name();
                 let component = component.to_str().unwrap();
-                for arch in &[
-                    "binary-amd64",
-                    "binary-arm64",
-                    "binary-armel",
-                    "binary-armhf",
-                    "binary-i386",
-                    "binary-mips",
-                    "binary-mipsel",
-                    "binary-mips64el",
-                    "binary-ppc64el",
-                    "binary-s390x",
-                    "binary-all",
-                    "source",
-                ] {
-                    let _ = fs::create_dir_all([&base, "/", component, "/", arch].concat());
+                let directories = architectures
+                    .with_all()
+                    .map(arch::binary_dir)
+                    .chain(Some("source".to_owned()));
+
+                for directory in directories {
+                    let _ = fs::create_dir_all([base, "/", component, "/", &directory].concat());
                 }
 
                 components.push(component.to_owned());
@@ -0,0 +1,336 @@
+use super::download::Limiter;
+use super::update::latest_version;
+use crate::command::Command;
+use crate::config::{Config, Direct, Source, SourceLocation};
+use crate::misc;
+use crate::url::UrlTokenizer;
+use deb_version::compare_versions;
+use futures_util::future::join_all;
+use reqwest::Client;
+use reqwest::header::USER_AGENT;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Current,
+    Outdated,
+    /// There is no way to find the upstream version of the package.
+    Unknown,
+    Error,
+}
+
+/// The version of a package in the config, compared to the newest version that is upstream.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub suite: String,
+    pub name: String,
+    pub kind: &'static str,
+    pub current: Option<String>,
+    pub upstream: Option<String>,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report {
+    fn new(
+        config: &Config,
+        name: &str,
+        kind: &'static str,
+        current: Option<String>,
+        upstream: anyhow::Result<Option<String>>,
+        is_newer: fn(&str, &str) -> bool,
+    ) -> Self {
+        let (upstream, status, error) = match upstream {
+            Ok(Some(upstream)) => {
+                let status = match current {
+                    Some(ref current) if !is_newer(&upstream, current) => Status::Current,
+                    Some(_) => Status::Outdated,
+                    None => Status::Unknown,
+                };
+
+                (Some(upstream), status, None)
+            }
+            Ok(None) => (None, Status::Unknown, None),
+            Err(why) => (None, Status::Error, Some(why.to_string())),
+        };
+
+        Report {
+            suite: config.archive.clone(),
+            name: name.to_owned(),
+            kind,
+            current,
+            upstream,
+            status,
+            error,
+        }
+    }
+}
+
+/// Checks the upstream version of every direct package, and every source in a git repository,
+/// and prints a report of them as a table, or as JSON Lines with one object per package.
+///
+/// Upstream versions are checked concurrently, within the download limits of the suite.
+pub async fn outdated(config: &Config, json: bool) {
+    let client = Client::new();
+    let limiter = Limiter::new(config.download_jobs, config.download_jobs_per_host);
+
+    let directs = join_all(config.direct.iter().flatten().map(|package| async {
+        let upstream = direct_upstream(&client, &limiter, package).await;
+        Report::new(
+            config,
+            &package.name,
+            "direct",
+            Some(package.version.clone()),
+            upstream,
+            |upstream, current| compare_versions(upstream, current) == Ordering::Greater,
+        )
+    }));
+
+    let sources = config.source.iter().flatten().filter_map(|source| {
+        let Some(SourceLocation::Git {
+            git,
+            branch,
+            commit,
+        }) = &source.location
+        else {
+            return None;
+        };
+
+        Some(async {
+            let refs = ls_remote(&limiter, git).await;
+            git_report(config, source, refs, branch.as_deref(), commit)
+        })
+    });
+
+    let (mut reports, sources) = futures_util::join!(directs, join_all(sources));
+    reports.extend(sources);
+
+    if json {
+        for report in &reports {
+            match serde_json::to_string(report) {
+                Ok(line) => println!("{}", line),
+                Err(why) => log::error!("failed to serialize report: {}", why),
+            }
+        }
+    } else {
+        print_table(&config.archive, &reports);
+    }
+}
+
+/// Finds the upstream version with the update rule of the package, or from the latest
+/// release on GitHub if the package is downloaded from there.
+async fn direct_upstream(
+    client: &Client,
+    limiter: &Limiter,
+    package: &Direct,
+) -> anyhow::Result<Option<String>> {
+    let (name, version) = (package.name.as_str(), package.version.as_str());
+    if let Some(ref update) = package.update {
+        let _permit = limiter.acquire(&update.url).await;
+        return latest_version(client, name, version, update)
+            .await
+            .map(Some);
+    }
+
+    let Some(path) = package.urls.first() else {
+        return Ok(None);
+    };
+
+    let url = UrlTokenizer::finalize(&path.url, path.name.as_deref().unwrap_or(name), version)
+        .map_err(|text| anyhow::anyhow!("unsupported variable: {}", text))?;
+
+    let Some(repo) = github_repo(&url) else {
+        return Ok(None);
+    };
+
+    #[derive(serde::Deserialize)]
+    struct Release {
+        tag_name: String,
+    }
+
+    let url = format!("https://api.github.com/repos/{}/releases/latest", repo);
+    let _permit = limiter.acquire(&url).await;
+    let release = client
+        .get(&url)
+        .header(USER_AGENT, "debrep")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let release: Release = serde_json::from_str(&release)?;
+    Ok(Some(tag_version(&release.tag_name, name).to_owned()))
+}
+
+/// The `owner/repo` of a URL on GitHub.
+fn github_repo(url: &str) -> Option<String> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("http://github.com/"))?;
+
+    let mut components = path.split('/');
+    match (components.next(), components.next()) {
+        (Some(owner), Some(repo)) if !owner.is_empty() && !repo.is_empty() => {
+            Some([owner, "/", repo.trim_end_matches(".git")].concat())
+        }
+        _ => None,
+    }
+}
+
+/// Strips the prefix of a tag, such as `v` or `name-`, to get its version.
+fn tag_version<'a>(tag: &'a str, name: &str) -> &'a str {
+    let tag = tag
+        .strip_prefix(name)
+        .and_then(|tag| tag.strip_prefix(['-', '_']))
+        .unwrap_or(tag);
+
+    tag.strip_prefix(['v', 'V']).unwrap_or(tag)
+}
+
+/// Lists the tags and heads of the remote repository, without blocking the other checks.
+async fn ls_remote(limiter: &Limiter, git: &str) -> anyhow::Result<String> {
+    let _permit = limiter.acquire(git).await;
+    let git = git.to_owned();
+    let refs = tokio::task::spawn_blocking(move || {
+        Command::new("git")
+            .args(["ls-remote", "--tags", "--heads", &git])
+            .run_with_stdout()
+    })
+    .await?;
+
+    Ok(refs?)
+}
+
+/// Sources with a version are compared to the newest tag in the remote repository. Otherwise,
+/// the commit that the source is pinned to, or that it was last built from, is compared to
+/// the head of its branch.
+fn git_report(
+    config: &Config,
+    source: &Source,
+    refs: anyhow::Result<String>,
+    branch: Option<&str>,
+    commit: &Option<String>,
+) -> Report {
+    let name = source.name.as_str();
+    if let Some(ref version) = source.version {
+        let upstream = refs.map(|refs| {
+            refs.lines()
+                .filter_map(|line| line.split('\t').nth(1)?.strip_prefix("refs/tags/"))
+                .filter(|tag| !tag.ends_with("^{}"))
+                .map(|tag| tag_version(tag, name))
+                .filter(|tag| tag.starts_with(|c: char| c.is_ascii_digit()))
+                .max_by(|a, b| compare_versions(a, b))
+                .map(String::from)
+        });
+
+        return Report::new(
+            config,
+            name,
+            "git",
+            Some(version.clone()),
+            upstream,
+            |upstream, current| compare_versions(upstream, current) == Ordering::Greater,
+        );
+    }
+
+    let branch = branch.unwrap_or("master");
+    let upstream = refs.map(|refs| {
+        let head = ["refs/heads/", branch].concat();
+        refs.lines()
+            .find(|line| line.split('\t').nth(1) == Some(head.as_str()))
+            .and_then(|line| line.split('\t').next())
+            .map(String::from)
+    });
+
+    let current = commit
+        .clone()
+        .or_else(|| recorded_commit(&config.archive, name, branch));
+
+    Report::new(
+        config,
+        name,
+        "git",
+        current,
+        upstream,
+        |upstream, current| !upstream.starts_with(current),
+    )
+}
+
+/// The commit of the branch that the source was last built from.
+fn recorded_commit(suite: &str, name: &str, branch: &str) -> Option<String> {
+    let record = misc::read_to_string(Path::new(&["record/", suite, "/", name].concat())).ok()?;
+    let mut lines = record.lines();
+    if lines.next() != Some("commit") {
+        return None;
+    }
+
+    lines
+        .filter_map(|line| line.split_once(' '))
+        .filter(|&(recorded, _)| recorded == branch)
+        .map(|(_, commit)| commit.to_owned())
+        .next_back()
+}
+
+fn print_table(suite: &str, reports: &[Report]) {
+    let rows: Vec<[String; 5]> = reports
+        .iter()
+        .map(|report| {
+            let status = match report.status {
+                Status::Current => "current",
+                Status::Outdated => "outdated",
+                Status::Unknown => "unknown",
+                Status::Error => report.error.as_deref().unwrap_or("error"),
+            };
+
+            [
+                report.name.clone(),
+                report.kind.to_owned(),
+                report.current.clone().unwrap_or_else(|| "-".into()),
+                report.upstream.clone().unwrap_or_else(|| "-".into()),
+                status.to_owned(),
+            ]
+        })
+        .collect();
+
+    let header = ["PACKAGE", "KIND", "CURRENT", "UPSTREAM", "STATUS"].map(String::from);
+    let mut widths = [0; 5];
+    for row in rows.iter().chain(Some(&header)) {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    println!("{}:", suite);
+    for row in Some(&header).into_iter().chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        println!("  {}", line.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upstream_names() {
+        assert_eq!(
+            github_repo("https://github.com/pop-os/debrepbuild/releases/download/v1.0/a.deb"),
+            Some("pop-os/debrepbuild".into())
+        );
+        assert_eq!(github_repo("https://example.com/pop-os/debrepbuild"), None);
+        assert_eq!(tag_version("v1.2.0", "foo"), "1.2.0");
+        assert_eq!(tag_version("foo-1.2.0", "foo"), "1.2.0");
+        assert_eq!(tag_version("foobar-1.2.0", "foo"), "foobar-1.2.0");
+    }
+}
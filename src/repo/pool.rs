@@ -1,5 +1,5 @@
 use crate::debian::DEB_SOURCE_EXTENSIONS;
-use crate::misc;
+use crate::debian::arch::{self, Architectures};
 use std::path::{Path, PathBuf};
 use std::{fs, io};
 
@@ -10,6 +10,7 @@ pub fn mv_to_pool<P: AsRef<Path>>(
     path: P,
     suite: &str,
     component: &str,
+    architectures: &Architectures,
     flags: u8,
     filter: Option<&str>,
 ) -> io::Result<()> {
@@ -23,6 +24,7 @@ pub fn mv_to_pool<P: AsRef<Path>>(
         path.as_ref(),
         suite,
         component,
+        architectures,
         flags,
         |src, dst| {
             if flags & KEEP_SOURCE != 0 || !is_source(src) {
@@ -45,13 +47,14 @@ fn is_source(src: &Path) -> bool {
 
 fn is_archive(src: &Path) -> bool {
     let path = src.to_str().unwrap();
-    path.ends_with(".deb") || path.ends_with(".ddeb")
+    path.ends_with(".deb") || path.ends_with(".ddeb") || path.ends_with(".udeb")
 }
 
 fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
     path: &Path,
     suite: &str,
     component: &str,
+    architectures: &Architectures,
     flags: u8,
     action: F,
     filter: Option<&str>,
@@ -68,7 +71,8 @@ fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
         if let (Some(filename), Some(filestem)) = (filename, filestem) {
             if let Some(name) = filter {
                 if !(filename.starts_with(&[name, "_"].concat())
-                    || filename.starts_with(&[name, "-dbgsym_"].concat()))
+                    || filename.starts_with(&[name, "-dbgsym_"].concat())
+                    || filename.starts_with(&[name, "-udeb_"].concat()))
                 {
                     continue;
                 }
@@ -95,11 +99,14 @@ fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
                     .concat(),
                 )
             } else {
-                if package.ends_with("-dbgsym") {
-                    package = &package[..package.len() - 7];
+                if let Some(name) = package
+                    .strip_suffix("-dbgsym")
+                    .or_else(|| package.strip_suffix("-udeb"))
+                {
+                    package = name;
                 }
 
-                let arch = misc::get_arch_from_stem(filestem);
+                let arch = arch::binary_dir(architectures.from_stem(filestem));
 
                 PathBuf::from(
                     [
@@ -107,8 +114,8 @@ fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
                         suite,
                         "/",
                         component,
-                        "/binary-",
-                        arch,
+                        "/",
+                        &arch,
                         "/",
                         &package[0..1],
                         "/",
@@ -0,0 +1,102 @@
+use crate::compress::BY_HASH;
+use crate::misc;
+use std::path::Path;
+use std::{fs, io};
+use walkdir::WalkDir;
+
+/// Where the dist files of a suite are generated, before they are published.
+pub const STAGING_DISTS: &str = "dists.staging/";
+/// Where the previously-published dist files of a suite are kept, for rolling back.
+pub const PREVIOUS_DISTS: &str = "dists.previous/";
+
+/// Creates an empty staging directory for the dist files.
+///
+/// Indices in the by-hash directories of the live dists will be linked into the staging
+/// directory, so that their previous generations continue to be served after publishing.
+pub fn prepare(live: &Path, staging: &Path, by_hash: bool) -> io::Result<()> {
+    if staging.exists() {
+        log::info!("removing stale dist files at {}", staging.display());
+        fs::remove_dir_all(staging)?;
+    }
+
+    fs::create_dir_all(staging)?;
+
+    if !by_hash || !live.exists() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(live).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let in_by_hash = path
+            .parent()
+            .is_some_and(|parent| BY_HASH.iter().any(|directory| parent.ends_with(directory)));
+        if !in_by_hash || !entry.file_type().is_file() {
+            continue;
+        }
+
+        let destination = staging.join(path.strip_prefix(live).unwrap());
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::hard_link(path, &destination)?;
+    }
+
+    Ok(())
+}
+
+/// Atomically replaces the live dist files with the staged dist files, and keeps the
+/// replaced dist files as the rollback target.
+///
+/// This is only atomic where the file system supports exchanging paths; see `misc::exchange`.
+pub fn publish(staging: &Path, live: &Path, previous: &Path) -> io::Result<()> {
+    log::info!("publishing dist files from {}", staging.display());
+    if !live.exists() {
+        return rename(staging, live);
+    }
+
+    misc::exchange(staging, live)?;
+
+    if previous.exists() {
+        fs::remove_dir_all(previous)?;
+    }
+
+    rename(staging, previous)
+}
+
+/// Atomically swaps the live dist files with the previously-published dist files.
+///
+/// Rolling back a second time will restore the dist files that were rolled back.
+pub fn rollback(live: &Path, previous: &Path) -> io::Result<()> {
+    if !previous.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "there are no previously-published dist files",
+        ));
+    }
+
+    log::info!("restoring dist files from {}", previous.display());
+    if live.exists() {
+        misc::exchange(previous, live)
+    } else {
+        rename(previous, live)
+    }
+}
+
+/// Moves the live dist files of a suite which is no longer published to the rollback target.
+pub fn retire(live: &Path, previous: &Path) -> io::Result<()> {
+    log::info!("retiring dist files at {}", live.display());
+    if previous.exists() {
+        fs::remove_dir_all(previous)?;
+    }
+
+    rename(live, previous)
+}
+
+fn rename(src: &Path, dst: &Path) -> io::Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::rename(src, dst)
+}
@@ -0,0 +1,119 @@
+use crate::checksum::{Algorithm, Checksum};
+use crate::config::{Config, Direct, Update, UpdateSource};
+use crate::url::UrlTokenizer;
+use deb_version::compare_versions;
+use hex_view::HexView;
+use reqwest::Client;
+use std::cmp::Ordering;
+
+/// Checks each direct package with an `update` rule for a newer version. When one is found, the
+/// artifacts of the new version are downloaded to update their checksums in the config. Returns
+/// how many packages were updated, and the errors of the packages that could not be updated.
+pub async fn update(config: &mut Config) -> (usize, Vec<String>) {
+    let client = Client::new();
+    let mut updated = 0;
+    let mut errors = Vec::new();
+
+    for package in config.direct.iter_mut().flatten() {
+        if package.update.is_none() {
+            continue;
+        }
+
+        match update_package(&client, package).await {
+            Ok(true) => updated += 1,
+            Ok(false) => (),
+            Err(why) => {
+                let msg = format!("package '{}' failed to update: {}", package.name, why);
+                log::error!("{}", msg);
+                errors.push(msg);
+            }
+        }
+    }
+
+    (updated, errors)
+}
+
+async fn update_package(client: &Client, package: &mut Direct) -> anyhow::Result<bool> {
+    let Direct {
+        name,
+        version,
+        urls,
+        update: Some(update),
+        ..
+    } = package
+    else {
+        return Ok(false);
+    };
+
+    log::info!("checking for a newer version of {}", name);
+    let latest = latest_version(client, name, version, update).await?;
+    if compare_versions(&latest, version) != Ordering::Greater {
+        log::info!("{} is up to date at {}", name, version);
+        return Ok(false);
+    }
+
+    log::info!("updating {} from {} to {}", name, version, latest);
+    for path in urls.iter_mut() {
+        let file_name = path.name.as_deref().unwrap_or(name);
+        if let Some(ref build_from) = update.build_from
+            && !build_from.iter().any(|x| x == file_name)
+        {
+            continue;
+        }
+
+        // Checksums which are looked up in a remote checksums file are not pinned.
+        if path.checksum.is_none() && path.checksums.is_some() {
+            continue;
+        }
+
+        let url = UrlTokenizer::finalize(&path.url, file_name, &latest)
+            .map_err(|text| anyhow::anyhow!("unsupported variable: {}", text))?;
+
+        let algorithm = match path.checksum {
+            Some(ref checksum) => Checksum::parse(checksum)?.algorithm,
+            None => Algorithm::default(),
+        };
+
+        let digest = digest(client, &url, algorithm).await?;
+        path.checksum = Some(algorithm.format(&digest));
+    }
+
+    *version = latest;
+    Ok(true)
+}
+
+/// Finds the newest version that the update rule of a package points to.
+pub(super) async fn latest_version(
+    client: &Client,
+    name: &str,
+    version: &str,
+    update: &Update,
+) -> anyhow::Result<String> {
+    let url = UrlTokenizer::finalize(&update.url, name, version)
+        .map_err(|text| anyhow::anyhow!("unsupported variable: {}", text))?;
+
+    let response = client.get(&url).send().await?.error_for_status()?;
+    let text = match update.source {
+        UpdateSource::Page => response.text().await?,
+        UpdateSource::Redirect => response.url().as_str().to_owned(),
+    };
+
+    update
+        .versions(&text)
+        .into_iter()
+        .max_by(|a, b| compare_versions(a, b))
+        .map(String::from)
+        .ok_or_else(|| anyhow::anyhow!("no versions were found at {}", url))
+}
+
+/// Downloads the file at the URL to compute its digest.
+async fn digest(client: &Client, url: &str, algorithm: Algorithm) -> anyhow::Result<String> {
+    log::info!("downloading {} to compute its checksum", url);
+    let mut response = client.get(url).send().await?.error_for_status()?;
+    let mut hasher = algorithm.hasher();
+    while let Some(chunk) = response.chunk().await? {
+        hasher.update(&chunk);
+    }
+
+    Ok(format!("{:x}", HexView::from(&*hasher.finalize())))
+}
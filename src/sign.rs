@@ -0,0 +1,364 @@
+//! In-process OpenPGP signing of the release files, as an alternative to calling `gpg`, and
+//! verification of the signatures of downloaded checksum files.
+
+use crate::config::{KeyLocation, SigningKey};
+use crate::misc;
+use pgp::composed::{
+    ArmorOptions, CleartextSignedMessage, Deserializable, DetachedSignature, SignedKeyDetails,
+    SignedPublicKey, SignedPublicSubKey, SignedSecretKey,
+};
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::packet::{KeyFlags, PublicKey, Signature, SignatureType};
+use pgp::types::{KeyDetails, Password, SigningKey as PgpSigningKey, Tag, Timestamp};
+use rand::thread_rng;
+use std::{env, io};
+
+/// The secret keys that the release files will be signed with, in their configured order.
+pub struct SecretKeys(Vec<(SignedSecretKey, Password)>);
+
+impl SecretKeys {
+    /// Reads and parses each of the armored secret keys in the signing config.
+    pub fn load(keys: &[SigningKey]) -> io::Result<Self> {
+        if keys.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "native signing requires at least one key in [[signing.keys]]",
+            ));
+        }
+
+        keys.iter()
+            .map(|key| {
+                let (armored, origin) = match key.location {
+                    KeyLocation::Path { ref path } => {
+                        (misc::read_to_string(path)?, path.display().to_string())
+                    }
+                    KeyLocation::Env { ref env } => (read_env(env)?, ["$", env].concat()),
+                };
+
+                let (secret, _) = SignedSecretKey::from_string(&armored).map_err(|why| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("failed to parse secret key from {}: {}", origin, why),
+                    )
+                })?;
+
+                let password = match key.password_env {
+                    Some(ref env) => Password::from(read_env(env)?),
+                    None => Password::empty(),
+                };
+
+                Ok((secret, password))
+            })
+            .collect::<io::Result<Vec<_>>>()
+            .map(SecretKeys)
+    }
+
+    /// Creates a cleartext-signed message from the text, with a signature from each key.
+    pub fn clearsign(&self, text: &str) -> io::Result<String> {
+        let signers = self.signers()?;
+        // As with `gpg --clearsign`, the final line break is not a part of the signed text.
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        CleartextSignedMessage::new_many(text, |normalized| {
+            signers
+                .iter()
+                .map(|(key, password)| {
+                    DetachedSignature::sign_text_data(
+                        thread_rng(),
+                        &Box::new(*key),
+                        password,
+                        HashAlgorithm::Sha512,
+                        normalized.as_bytes(),
+                    )
+                    .map(|detached| detached.signature)
+                })
+                .collect()
+        })
+        .and_then(|message| message.to_armored_string(ArmorOptions::default()))
+        .map_err(io::Error::other)
+    }
+
+    /// Creates an armored detached signature of the data, with a signature from each key.
+    pub fn detach_sign(&self, data: &[u8]) -> io::Result<String> {
+        let signatures = self
+            .signers()?
+            .iter()
+            .map(|(key, password)| {
+                DetachedSignature::sign_binary_data(
+                    thread_rng(),
+                    &Box::new(*key),
+                    password,
+                    HashAlgorithm::Sha512,
+                    data,
+                )
+            })
+            .collect::<pgp::errors::Result<Vec<_>>>()
+            .map_err(io::Error::other)?;
+
+        let mut output = Vec::new();
+        pgp::armor::write(
+            &signatures,
+            pgp::armor::BlockType::Signature,
+            &mut output,
+            None,
+            true,
+        )
+        .map_err(io::Error::other)?;
+
+        String::from_utf8(output).map_err(io::Error::other)
+    }
+
+    /// The key that each of the secret keys will make its signatures with. They are boxed when
+    /// signing, as only `Box<&dyn SigningKey>` implements the signing trait.
+    fn signers(&self) -> io::Result<Vec<(&dyn PgpSigningKey, &Password)>> {
+        self.0
+            .iter()
+            .map(|(key, password)| signing_key(key).map(|signer| (signer, password)))
+            .collect()
+    }
+}
+
+/// Selects the newest subkey which is bound to the primary key and may sign, as `gpg` does. The
+/// primary key is only used if there is no such subkey, and it may sign itself.
+fn signing_key(key: &SignedSecretKey) -> io::Result<&dyn PgpSigningKey> {
+    let primary = key.primary_key.public_key();
+    let subkey = key
+        .secret_subkeys
+        .iter()
+        .filter(|subkey| subkey_can_sign(primary, &subkey.signed_public_key()))
+        .max_by_key(|subkey| subkey.key.created_at());
+
+    if let Some(subkey) = subkey {
+        return Ok(&subkey.key);
+    }
+
+    if primary_can_sign(primary, &key.details) {
+        return Ok(&key.primary_key);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("key {} has no valid key for signing", key.fingerprint()),
+    ))
+}
+
+/// Whether the primary key may sign, by the flags of its newest self-signature. Keys whose
+/// self-signatures have no flags predate them, and may sign.
+fn primary_can_sign(primary: &PublicKey, details: &SignedKeyDetails) -> bool {
+    let revoked = details
+        .revocation_signatures
+        .iter()
+        .any(|signature| signature.verify_key(primary).is_ok());
+
+    if revoked {
+        return false;
+    }
+
+    let direct = details
+        .direct_signatures
+        .iter()
+        .filter(|signature| signature.verify_key(primary).is_ok());
+
+    let certifications = details.users.iter().flat_map(|user| {
+        user.signatures.iter().filter(move |signature| {
+            signature
+                .verify_certification(primary, Tag::UserId, &user.id)
+                .is_ok()
+        })
+    });
+
+    direct
+        .chain(certifications)
+        .max_by_key(|signature| signature.created())
+        .is_some_and(|signature| {
+            let flags = signature.key_flags();
+            (flags.sign() || flags == KeyFlags::default())
+                && !expired(primary.created_at(), signature)
+        })
+}
+
+/// Whether the subkey is bound to the primary key, has not been revoked, and its newest binding
+/// signature allows it to sign.
+fn subkey_can_sign(primary: &PublicKey, subkey: &SignedPublicSubKey) -> bool {
+    // The embedded back signature of a signing subkey is verified along with its bindings.
+    if subkey.verify_bindings(primary).is_err() {
+        return false;
+    }
+
+    let bindings = subkey.signatures.iter();
+    if bindings
+        .clone()
+        .any(|signature| signature.typ() == Some(SignatureType::SubkeyRevocation))
+    {
+        return false;
+    }
+
+    bindings
+        .max_by_key(|signature| signature.created())
+        .is_some_and(|binding| {
+            binding.key_flags().sign() && !expired(subkey.key.created_at(), binding)
+        })
+}
+
+/// Whether the key, which was created at the given time, has expired by its self-signature.
+fn expired(created: Timestamp, signature: &Signature) -> bool {
+    signature
+        .key_expiration_time()
+        .filter(|expiration| expiration.as_secs() != 0)
+        .is_some_and(|expiration| {
+            u64::from(created.as_secs()) + u64::from(expiration.as_secs())
+                <= u64::from(Timestamp::now().as_secs())
+        })
+}
+
+/// Verifies that the detached signature, which may be armored or binary, was made over the
+/// data by one of the armored public keys, or by one of their bound signing subkeys.
+pub fn verify_detached(keys: &str, signature: &[u8], data: &[u8]) -> io::Result<()> {
+    let (keys, _) = SignedPublicKey::from_string_many(keys).map_err(io::Error::other)?;
+    let keys = keys
+        .collect::<pgp::errors::Result<Vec<_>>>()
+        .map_err(io::Error::other)?;
+
+    let signatures = if signature.starts_with(b"-----BEGIN") {
+        DetachedSignature::from_armor_many(signature)
+            .map_err(io::Error::other)?
+            .0
+            .collect::<pgp::errors::Result<Vec<_>>>()
+    } else {
+        DetachedSignature::from_bytes_many(signature)
+            .map_err(io::Error::other)?
+            .collect::<pgp::errors::Result<Vec<_>>>()
+    }
+    .map_err(io::Error::other)?;
+
+    // Only the keys which may sign are trusted, as `gpg --verify` would trust them.
+    let verified = signatures.iter().any(|signature| {
+        keys.iter().any(|key| {
+            let primary = &key.primary_key;
+            (primary_can_sign(primary, &key.details) && signature.verify(primary, data).is_ok())
+                || key.public_subkeys.iter().any(|subkey| {
+                    subkey_can_sign(primary, subkey) && signature.verify(subkey, data).is_ok()
+                })
+        })
+    });
+
+    if verified {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "signature was not made by a trusted key",
+        ))
+    }
+}
+
+fn read_env(variable: &str) -> io::Result<String> {
+    env::var(variable).map_err(|why| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("failed to read ${}: {}", variable, why),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgp::composed::{KeyType, SecretKeyParamsBuilder, SubkeyParamsBuilder};
+
+    /// Generates a key whose primary key may only sign if it has no signing subkey.
+    fn generate(signing_subkey: bool) -> SignedSecretKey {
+        let mut params = SecretKeyParamsBuilder::default();
+        params
+            .key_type(KeyType::Ed25519Legacy)
+            .can_certify(true)
+            .can_sign(!signing_subkey)
+            .primary_user_id("Test <test@example.com>".into());
+
+        if signing_subkey {
+            let subkey = SubkeyParamsBuilder::default()
+                .key_type(KeyType::Ed25519Legacy)
+                .can_sign(true)
+                .build()
+                .unwrap();
+            params.subkeys(vec![subkey]);
+        }
+
+        params.build().unwrap().generate(thread_rng()).unwrap()
+    }
+
+    /// Signs with the secret key, and verifies the signatures with its public key.
+    fn round_trip(key: SignedSecretKey) -> (SignedPublicKey, DetachedSignature) {
+        let public = SignedPublicKey::from(key.clone());
+        let armored = public.to_armored_string(ArmorOptions::default()).unwrap();
+        let keys = SecretKeys(vec![(key, Password::empty())]);
+
+        let signature = keys.detach_sign(b"Release").unwrap();
+        verify_detached(&armored, signature.as_bytes(), b"Release").unwrap();
+        assert!(verify_detached(&armored, signature.as_bytes(), b"Tampered").is_err());
+
+        let (message, _) =
+            CleartextSignedMessage::from_string(&keys.clearsign("Release\n").unwrap()).unwrap();
+        assert_eq!(message.signed_text(), "Release");
+
+        let (signature, _) = DetachedSignature::from_string(&signature).unwrap();
+        (public, signature)
+    }
+
+    #[test]
+    fn sign_with_primary_key() {
+        let (public, signature) = round_trip(generate(false));
+        assert!(signature.verify(&public.primary_key, b"Release").is_ok());
+    }
+
+    #[test]
+    fn sign_with_signing_subkey() {
+        let (public, signature) = round_trip(generate(true));
+        assert!(
+            signature
+                .verify(&public.public_subkeys[0], b"Release")
+                .is_ok()
+        );
+        assert!(signature.verify(&public.primary_key, b"Release").is_err());
+    }
+
+    #[test]
+    fn verify_signatures_of_keys_which_may_sign() {
+        let key = generate(true);
+        let keys = SecretKeys(vec![(key.clone(), Password::empty())]);
+        let signature = keys.detach_sign(b"SHA256SUMS").unwrap();
+        let armored =
+            |public: &SignedPublicKey| public.to_armored_string(ArmorOptions::default()).unwrap();
+
+        let public = SignedPublicKey::from(key.clone());
+        verify_detached(&armored(&public), signature.as_bytes(), b"SHA256SUMS").unwrap();
+        assert!(verify_detached(&armored(&public), signature.as_bytes(), b"Tampered").is_err());
+
+        // A subkey which is not bound to the primary key is not trusted.
+        let mut unbound = public.clone();
+        unbound.public_subkeys[0].signatures.clear();
+        assert!(verify_detached(&armored(&unbound), signature.as_bytes(), b"SHA256SUMS").is_err());
+
+        // Nor is a subkey which is bound to another primary key.
+        let mut transplanted = SignedPublicKey::from(generate(true));
+        transplanted.public_subkeys = public.public_subkeys.clone();
+        assert!(
+            verify_detached(&armored(&transplanted), signature.as_bytes(), b"SHA256SUMS").is_err()
+        );
+
+        // Nor is a primary key whose flags do not allow it to sign.
+        let signature = DetachedSignature::sign_binary_data(
+            thread_rng(),
+            &Box::new(&key.primary_key as &dyn PgpSigningKey),
+            &Password::empty(),
+            HashAlgorithm::Sha512,
+            &b"SHA256SUMS"[..],
+        )
+        .unwrap();
+
+        assert!(signature.verify(&public.primary_key, b"SHA256SUMS").is_ok());
+        let signature = signature
+            .to_armored_string(ArmorOptions::default())
+            .unwrap();
+        assert!(verify_detached(&armored(&public), signature.as_bytes(), b"SHA256SUMS").is_err());
+    }
+}